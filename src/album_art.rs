@@ -0,0 +1,285 @@
+use crate::config::{ArtworkFormat, ArtworkOptions};
+use anyhow::Result;
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::png::PngEncoder;
+use image::imageops::FilterType;
+use image::DynamicImage;
+use lofty::picture::MimeType;
+use std::path::Path;
+use tracing::debug;
+
+/// Default JPEG quality used when re-encoding album art
+pub const DEFAULT_JPEG_QUALITY: u8 = 90;
+
+/// Lowest JPEG quality tried when shrinking album art to `max_bytes`
+const MIN_JPEG_QUALITY: u8 = 30;
+
+/// Album art ready to be embedded
+#[derive(Debug, Clone)]
+pub struct AlbumArt {
+    pub mime_type: MimeType,
+    pub data: Vec<u8>,
+}
+
+/// Detect the image type from the leading magic bytes
+pub fn detect_mime_type(data: &[u8]) -> Option<MimeType> {
+    if data.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        Some(MimeType::Jpeg)
+    } else if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some(MimeType::Png)
+    } else if data.starts_with(b"GIF87a") || data.starts_with(b"GIF89a") {
+        Some(MimeType::Gif)
+    } else if data.starts_with(b"BM") {
+        Some(MimeType::Bmp)
+    } else if data.starts_with(b"II*\0") || data.starts_with(b"MM\0*") {
+        Some(MimeType::Tiff)
+    } else {
+        None
+    }
+}
+
+/// The image format to write for a file name such as `cover.jpg`
+pub fn format_for_path(path: &Path) -> Option<ArtworkFormat> {
+    match path.extension()?.to_str()?.to_ascii_lowercase().as_str() {
+        "jpg" | "jpeg" => Some(ArtworkFormat::Jpeg),
+        "png" => Some(ArtworkFormat::Png),
+        _ => None,
+    }
+}
+
+/// Read an album art file and apply the resize/re-encode options
+pub fn load_album_art(path: &Path, options: Option<&ArtworkOptions>) -> Result<AlbumArt> {
+    let data = std::fs::read(path)
+        .map_err(|e| anyhow::anyhow!("Failed to read album art file '{}': {}", path.display(), e))?;
+
+    let art = process_album_art(data, options)
+        .map_err(|e| anyhow::anyhow!("Invalid album art '{}': {}", path.display(), e))?;
+
+    debug!("Loaded album art '{}' ({:?}, {} bytes)", path.display(), art.mime_type, art.data.len());
+    Ok(art)
+}
+
+/// Validate image data and downscale or re-encode it as configured
+///
+/// The original bytes are kept when the image already satisfies every option.
+pub fn process_album_art(data: Vec<u8>, options: Option<&ArtworkOptions>) -> Result<AlbumArt> {
+    let mime_type = detect_mime_type(&data)
+        .ok_or_else(|| anyhow::anyhow!("Unsupported image format (expected JPEG, PNG, GIF, BMP or TIFF)"))?;
+
+    let Some(options) = options else {
+        return Ok(AlbumArt { mime_type, data });
+    };
+
+    let source_format = match mime_type {
+        MimeType::Jpeg => Some(ArtworkFormat::Jpeg),
+        MimeType::Png => Some(ArtworkFormat::Png),
+        _ => None,
+    };
+    let target_format = options.format.or(source_format).unwrap_or(ArtworkFormat::Jpeg);
+    let too_many_bytes = options.max_bytes.is_some_and(|max| data.len() as u64 > max);
+
+    if options.max_dimension.is_none() && !too_many_bytes && source_format == Some(target_format) {
+        return Ok(AlbumArt { mime_type, data });
+    }
+
+    let image = image::load_from_memory(&data)
+        .map_err(|e| anyhow::anyhow!("Failed to decode image: {}", e))?;
+
+    let too_large = options.max_dimension
+        .is_some_and(|max| image.width() > max || image.height() > max);
+    if !too_large && !too_many_bytes && source_format == Some(target_format) {
+        return Ok(AlbumArt { mime_type, data });
+    }
+
+    let image = match options.max_dimension {
+        Some(max) if too_large => {
+            let resized = image.resize(max, max, FilterType::Lanczos3);
+            debug!("Resized album art from {}x{} to {}x{}",
+                   image.width(), image.height(), resized.width(), resized.height());
+            resized
+        }
+        _ => image,
+    };
+
+    let quality = options.quality.unwrap_or(DEFAULT_JPEG_QUALITY);
+    let data = match target_format {
+        ArtworkFormat::Jpeg => encode_jpeg_within(&image, quality, options.max_bytes)?,
+        ArtworkFormat::Png => {
+            let data = encode_image(&image, ArtworkFormat::Png, quality)?;
+            if let Some(max) = options.max_bytes.filter(|max| data.len() as u64 > *max) {
+                anyhow::bail!("PNG image is {} bytes, larger than max_bytes {}; use format: jpeg", data.len(), max);
+            }
+            data
+        }
+    };
+
+    Ok(AlbumArt {
+        mime_type: mime_type_for(target_format),
+        data,
+    })
+}
+
+/// Encode album art in the given format, reusing the bytes when it already matches
+pub fn convert_album_art(art: &AlbumArt, format: ArtworkFormat, quality: u8) -> Result<Vec<u8>> {
+    if art.mime_type == mime_type_for(format) {
+        return Ok(art.data.clone());
+    }
+
+    let image = image::load_from_memory(&art.data)
+        .map_err(|e| anyhow::anyhow!("Failed to decode image: {}", e))?;
+    encode_image(&image, format, quality)
+}
+
+fn mime_type_for(format: ArtworkFormat) -> MimeType {
+    match format {
+        ArtworkFormat::Jpeg => MimeType::Jpeg,
+        ArtworkFormat::Png => MimeType::Png,
+    }
+}
+
+/// Encode as JPEG, lowering the quality in steps until the result fits in `max_bytes`
+fn encode_jpeg_within(image: &DynamicImage, quality: u8, max_bytes: Option<u64>) -> Result<Vec<u8>> {
+    let mut quality = quality;
+
+    loop {
+        let data = encode_image(image, ArtworkFormat::Jpeg, quality)?;
+        match max_bytes {
+            Some(max) if data.len() as u64 > max => {
+                if quality <= MIN_JPEG_QUALITY {
+                    anyhow::bail!(
+                        "Image is still {} bytes at JPEG quality {}, larger than max_bytes {}; lower max_dimension",
+                        data.len(), quality, max
+                    );
+                }
+                quality = quality.saturating_sub(10).max(MIN_JPEG_QUALITY);
+                debug!("Album art is {} bytes, retrying with JPEG quality {}", data.len(), quality);
+            }
+            _ => return Ok(data),
+        }
+    }
+}
+
+fn encode_image(image: &DynamicImage, format: ArtworkFormat, quality: u8) -> Result<Vec<u8>> {
+    let mut data = Vec::new();
+
+    match format {
+        ArtworkFormat::Jpeg => {
+            // JPEG has no alpha channel
+            let rgb = DynamicImage::ImageRgb8(image.to_rgb8());
+            rgb.write_with_encoder(JpegEncoder::new_with_quality(&mut data, quality))?;
+        }
+        ArtworkFormat::Png => {
+            image.write_with_encoder(PngEncoder::new(&mut data))?;
+        }
+    }
+
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage};
+
+    fn test_image(width: u32, height: u32, format: ArtworkFormat) -> Vec<u8> {
+        // A gradient with noise so JPEG quality affects the encoded size
+        let image = RgbImage::from_fn(width, height, |x, y| {
+            let noise = ((x * 7919 + y * 104729) % 61) as u8;
+            Rgb([(x % 256) as u8, (y % 256) as u8, noise.wrapping_mul(4)])
+        });
+        encode_image(&DynamicImage::ImageRgb8(image), format, 95).unwrap()
+    }
+
+    #[test]
+    fn test_detect_mime_type() {
+        assert_eq!(detect_mime_type(&test_image(4, 4, ArtworkFormat::Jpeg)), Some(MimeType::Jpeg));
+        assert_eq!(detect_mime_type(&test_image(4, 4, ArtworkFormat::Png)), Some(MimeType::Png));
+        assert_eq!(detect_mime_type(b"GIF89a\x01\x00"), Some(MimeType::Gif));
+        assert_eq!(detect_mime_type(b"BM\x00\x00"), Some(MimeType::Bmp));
+        assert_eq!(detect_mime_type(b"MM\x00*\x00"), Some(MimeType::Tiff));
+        assert_eq!(detect_mime_type(b"RIFF\x00\x00\x00\x00WEBP"), None);
+        assert_eq!(detect_mime_type(b""), None);
+    }
+
+    #[test]
+    fn test_process_album_art_unsupported() {
+        assert!(process_album_art(b"not an image".to_vec(), None).is_err());
+    }
+
+    #[test]
+    fn test_process_album_art_unchanged() {
+        let data = test_image(64, 32, ArtworkFormat::Png);
+        let options = ArtworkOptions {
+            max_dimension: Some(100),
+            ..Default::default()
+        };
+
+        let art = process_album_art(data.clone(), Some(&options)).unwrap();
+
+        assert_eq!(art.mime_type, MimeType::Png);
+        assert_eq!(art.data, data);
+    }
+
+    #[test]
+    fn test_process_album_art_resize_and_convert() {
+        let data = test_image(400, 200, ArtworkFormat::Png);
+        let options = ArtworkOptions {
+            max_dimension: Some(100),
+            format: Some(ArtworkFormat::Jpeg),
+            ..Default::default()
+        };
+
+        let art = process_album_art(data, Some(&options)).unwrap();
+
+        assert_eq!(art.mime_type, MimeType::Jpeg);
+        let image = image::load_from_memory(&art.data).unwrap();
+        assert_eq!((image.width(), image.height()), (100, 50));
+    }
+
+    #[test]
+    fn test_process_album_art_other_formats() {
+        let image = DynamicImage::ImageRgb8(RgbImage::from_fn(300, 150, |x, y| Rgb([x as u8, y as u8, 0])));
+        let options = ArtworkOptions {
+            max_dimension: Some(100),
+            ..Default::default()
+        };
+
+        for format in [image::ImageFormat::Gif, image::ImageFormat::Bmp, image::ImageFormat::Tiff] {
+            let mut data = std::io::Cursor::new(Vec::new());
+            image.write_to(&mut data, format).unwrap();
+
+            // Formats other than JPEG and PNG are decoded and written as JPEG
+            let art = process_album_art(data.into_inner(), Some(&options)).unwrap();
+            assert_eq!(art.mime_type, MimeType::Jpeg, "{:?}", format);
+            let resized = image::load_from_memory(&art.data).unwrap();
+            assert_eq!((resized.width(), resized.height()), (100, 50), "{:?}", format);
+        }
+    }
+
+    #[test]
+    fn test_process_album_art_max_bytes() {
+        let data = test_image(256, 256, ArtworkFormat::Jpeg);
+        let limit = data.len() as u64 / 2;
+        let options = ArtworkOptions {
+            max_bytes: Some(limit),
+            ..Default::default()
+        };
+
+        let art = process_album_art(data, Some(&options)).unwrap();
+        assert!(art.data.len() as u64 <= limit);
+
+        let options = ArtworkOptions {
+            max_bytes: Some(100),
+            ..Default::default()
+        };
+        assert!(process_album_art(test_image(256, 256, ArtworkFormat::Jpeg), Some(&options)).is_err());
+    }
+
+    #[test]
+    fn test_format_for_path() {
+        assert_eq!(format_for_path(Path::new("cover.jpg")), Some(ArtworkFormat::Jpeg));
+        assert_eq!(format_for_path(Path::new("folder.JPEG")), Some(ArtworkFormat::Jpeg));
+        assert_eq!(format_for_path(Path::new("cover.png")), Some(ArtworkFormat::Png));
+        assert_eq!(format_for_path(Path::new("cover.webp")), None);
+    }
+}
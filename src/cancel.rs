@@ -0,0 +1,34 @@
+use anyhow::Result;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static CANCELLED: AtomicBool = AtomicBool::new(false);
+
+/// Exit code of a run stopped by a signal, as a shell reports SIGINT
+pub const EXIT_CODE: i32 = 130;
+
+/// Turn Ctrl-C and SIGTERM into a request to stop the pipeline
+///
+/// Steps notice it at their next check, kill the FFmpeg process they are running and
+/// remove the output they were writing. A second signal exits on the spot.
+pub fn install_handler() -> Result<()> {
+    ctrlc::set_handler(|| {
+        if CANCELLED.swap(true, Ordering::SeqCst) {
+            eprintln!("Interrupted again, exiting without cleaning up");
+            std::process::exit(EXIT_CODE);
+        }
+        eprintln!("Interrupted, stopping the pipeline (press Ctrl-C again to exit immediately)");
+    })?;
+    Ok(())
+}
+
+pub fn is_cancelled() -> bool {
+    CANCELLED.load(Ordering::SeqCst)
+}
+
+/// Fail once the run has been interrupted
+pub fn check() -> Result<()> {
+    if is_cancelled() {
+        anyhow::bail!("Interrupted");
+    }
+    Ok(())
+}
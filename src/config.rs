@@ -1,5 +1,6 @@
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
 use std::path::Path;
 use crate::settings::Settings;
 
@@ -10,6 +11,8 @@ pub struct Config {
     pub formats: FormatsConfig,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub settings: Option<Settings>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub album: Option<TagDefaults>,
     pub steps: Vec<StepConfig>,
 }
 
@@ -43,7 +46,7 @@ pub struct SplitFile {
     pub end_seconds: f64,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct TagFile {
     pub file: String,
     pub title: Option<String>,
@@ -58,6 +61,374 @@ pub struct TagFile {
     pub genre: Option<String>,
     pub year: Option<u32>,
     pub comment: Option<String>,
+    pub lyrics: Option<String>,
+    pub custom: Option<BTreeMap<String, String>>,
+    pub images: Option<Vec<TagImage>>,
+    /// Full release date such as `2024-05-01`
+    pub date: Option<String>,
+    pub isrc: Option<String>,
+    pub musicbrainz: Option<MusicBrainzIds>,
+    /// Drop every existing field and picture before writing, overriding the step setting
+    pub clear_existing: Option<bool>,
+    /// Existing fields to remove before writing, in addition to the step's `remove`
+    pub remove: Option<Vec<String>>,
+}
+
+/// MusicBrainz identifiers written alongside the regular tags
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MusicBrainzIds {
+    pub release_id: Option<String>,
+    pub release_group_id: Option<String>,
+    pub release_artist_ids: Option<Vec<String>>,
+    pub track_id: Option<String>,
+    pub recording_id: Option<String>,
+    pub artist_ids: Option<Vec<String>>,
+}
+
+/// A saved MusicBrainz release (ws/2 JSON) to take tag values from
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MusicBrainzSource {
+    pub release: String,
+    #[serde(default = "default_medium")]
+    pub medium: u32,
+}
+
+fn default_medium() -> u32 {
+    1
+}
+
+/// Role of an embedded picture, following the ID3v2 APIC picture types
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ImageType {
+    Other,
+    Icon,
+    OtherIcon,
+    #[default]
+    FrontCover,
+    BackCover,
+    #[serde(alias = "booklet")]
+    Leaflet,
+    #[serde(alias = "disc")]
+    Media,
+    LeadArtist,
+    Artist,
+    Conductor,
+    Band,
+    Composer,
+    Lyricist,
+    RecordingLocation,
+    DuringRecording,
+    DuringPerformance,
+    ScreenCapture,
+    Illustration,
+    BandLogo,
+    PublisherLogo,
+}
+
+/// An extra picture to embed, in addition to `album_art`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TagImage {
+    pub file: String,
+    #[serde(rename = "type", default)]
+    pub image_type: ImageType,
+    pub description: Option<String>,
+}
+
+/// Album-level tag values shared by every file of a tag step
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TagDefaults {
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub album_artist: Option<String>,
+    pub track_total: Option<u32>,
+    pub disk: Option<u32>,
+    pub disk_total: Option<u32>,
+    pub album_art: Option<String>,
+    pub genre: Option<String>,
+    pub year: Option<u32>,
+    pub comment: Option<String>,
+    pub custom: Option<BTreeMap<String, String>>,
+    pub images: Option<Vec<TagImage>>,
+    pub date: Option<String>,
+}
+
+/// What a tag step does when written tags do not read back as configured
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TagVerifyMode {
+    /// Log the differences and continue
+    Warn,
+    /// Fail the step after every file has been checked
+    Fail,
+}
+
+/// Tag formats that can be stripped from a file besides the tag being written
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TagFormat {
+    Id3v1,
+    Id3v2,
+    Ape,
+    RiffInfo,
+}
+
+/// How ID3 tags are written, for players that only read older variants
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Id3Options {
+    #[serde(default)]
+    pub version: Id3Version,
+    /// Text encoding of ID3v2 frames (default: UTF-8 for ID3v2.4, UTF-16 for ID3v2.3)
+    pub encoding: Option<Id3Encoding>,
+    /// Also write an ID3v1 tag with the basic fields
+    #[serde(default)]
+    pub id3v1: bool,
+}
+
+/// ID3v2 version written by a tag step
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "Id3VersionValue")]
+pub enum Id3Version {
+    #[serde(rename = "2.3")]
+    V23,
+    #[default]
+    #[serde(rename = "2.4")]
+    V24,
+}
+
+/// `version: 2.3` is a number in YAML while `version: "2.3"` is a string
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Id3VersionValue {
+    Number(f64),
+    Text(String),
+}
+
+impl TryFrom<Id3VersionValue> for Id3Version {
+    type Error = String;
+
+    fn try_from(value: Id3VersionValue) -> std::result::Result<Self, Self::Error> {
+        let text = match value {
+            Id3VersionValue::Number(number) => number.to_string(),
+            Id3VersionValue::Text(text) => text,
+        };
+        match text.trim_start_matches(['v', 'V']) {
+            "2.3" => Ok(Id3Version::V23),
+            "2.4" => Ok(Id3Version::V24),
+            _ => Err(format!("unsupported ID3v2 version '{}', expected 2.3 or 2.4", text)),
+        }
+    }
+}
+
+/// Text encoding of ID3v2 frames
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Id3Encoding {
+    Latin1,
+    Utf16,
+    Utf16be,
+    Utf8,
+}
+
+/// Image format used when album art is re-encoded
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ArtworkFormat {
+    Jpeg,
+    Png,
+}
+
+/// How a tag step processes album art before embedding it
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ArtworkOptions {
+    /// Maximum width and height in pixels; larger images are downscaled
+    pub max_dimension: Option<u32>,
+    /// Maximum encoded size in bytes; JPEG quality is lowered until it fits
+    pub max_bytes: Option<u64>,
+    /// Re-encode to this format (defaults to the source format)
+    pub format: Option<ArtworkFormat>,
+    /// JPEG quality from 1 to 100 (default: 90)
+    pub quality: Option<u8>,
+    /// Image files such as `cover.jpg` or `folder.jpg` to write next to the tagged files
+    #[serde(default)]
+    pub save_as: Vec<String>,
+}
+
+/// A still frame extracted from a video with FFmpeg to use as album art
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VideoFrameArt {
+    pub input: String,
+    pub timestamp: String,
+    /// FFmpeg crop as `w:h` or `w:h:x:y`
+    pub crop: Option<String>,
+    #[serde(default = "default_video_frame_output")]
+    pub output: String,
+}
+
+fn default_video_frame_output() -> String {
+    "cover.jpg".to_string()
+}
+
+impl VideoFrameArt {
+    /// Use the extracted frame as album art for every file that has none
+    pub fn apply_to(&self, files: &mut [TagFile]) {
+        for file in files.iter_mut().filter(|file| file.album_art.is_none()) {
+            file.album_art = Some(self.output.clone());
+        }
+    }
+}
+
+impl TagFile {
+    /// Every picture to embed: `album_art` as the front cover followed by `images`
+    pub fn pictures(&self) -> Vec<TagImage> {
+        let album_art = self.album_art.iter().map(|file| TagImage {
+            file: file.clone(),
+            image_type: ImageType::FrontCover,
+            description: None,
+        });
+
+        album_art.chain(self.images.iter().flatten().cloned()).collect()
+    }
+
+    /// Fill every field that is not set on this entry from another entry
+    pub fn merged_with(&self, fallback: &TagFile) -> TagFile {
+        let custom = match (&fallback.custom, &self.custom) {
+            (Some(fallback_custom), Some(file_custom)) => {
+                let mut merged = fallback_custom.clone();
+                merged.extend(file_custom.iter().map(|(k, v)| (k.clone(), v.clone())));
+                Some(merged)
+            }
+            (fallback_custom, file_custom) => file_custom.clone().or_else(|| fallback_custom.clone()),
+        };
+
+        TagFile {
+            file: self.file.clone(),
+            title: self.title.clone().or_else(|| fallback.title.clone()),
+            artist: self.artist.clone().or_else(|| fallback.artist.clone()),
+            album: self.album.clone().or_else(|| fallback.album.clone()),
+            album_artist: self.album_artist.clone().or_else(|| fallback.album_artist.clone()),
+            track: self.track.or(fallback.track),
+            track_total: self.track_total.or(fallback.track_total),
+            disk: self.disk.or(fallback.disk),
+            disk_total: self.disk_total.or(fallback.disk_total),
+            album_art: self.album_art.clone().or_else(|| fallback.album_art.clone()),
+            genre: self.genre.clone().or_else(|| fallback.genre.clone()),
+            year: self.year.or(fallback.year),
+            comment: self.comment.clone().or_else(|| fallback.comment.clone()),
+            lyrics: self.lyrics.clone().or_else(|| fallback.lyrics.clone()),
+            custom,
+            images: self.images.clone().or_else(|| fallback.images.clone()),
+            date: self.date.clone().or_else(|| fallback.date.clone()),
+            isrc: self.isrc.clone().or_else(|| fallback.isrc.clone()),
+            musicbrainz: self.musicbrainz.clone().or_else(|| fallback.musicbrainz.clone()),
+            clear_existing: self.clear_existing.or(fallback.clear_existing),
+            remove: self.remove.clone().or_else(|| fallback.remove.clone()),
+        }
+    }
+}
+
+impl From<&TagDefaults> for TagFile {
+    fn from(defaults: &TagDefaults) -> Self {
+        TagFile {
+            artist: defaults.artist.clone(),
+            album: defaults.album.clone(),
+            album_artist: defaults.album_artist.clone(),
+            track_total: defaults.track_total,
+            disk: defaults.disk,
+            disk_total: defaults.disk_total,
+            album_art: defaults.album_art.clone(),
+            genre: defaults.genre.clone(),
+            year: defaults.year,
+            comment: defaults.comment.clone(),
+            custom: defaults.custom.clone(),
+            images: defaults.images.clone(),
+            date: defaults.date.clone(),
+            ..Default::default()
+        }
+    }
+}
+
+/// Give every file with a track number but no track total the number of distinct track
+/// numbers on the same disk
+///
+/// Runs on the fully merged values of the files being tagged, so track and disk numbers
+/// from file names, MusicBrainz or the source are counted as well. Entries without a track
+/// number and files matched by several entries do not add to the count.
+pub fn fill_track_totals(files: &mut [TagFile]) {
+    let tracks: BTreeSet<(Option<u32>, u32)> = files.iter()
+        .filter_map(|file| Some((file.disk, file.track?)))
+        .collect();
+    for file in files.iter_mut() {
+        if file.track.is_some() && file.track_total.is_none() {
+            let count = tracks.iter().filter(|(disk, _)| *disk == file.disk).count();
+            file.track_total = Some(count as u32);
+        }
+    }
+}
+
+/// What a cleanup step does with matched paths
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CleanupMode {
+    #[default]
+    Delete,
+    /// Move into a timestamped batch in `.soundpipeline-trash/`
+    Trash,
+}
+
+/// Whether a step runs depending on how the steps before it went
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RunCondition {
+    /// Only while no step has failed
+    #[default]
+    Success,
+    /// Only after an earlier step failed
+    Failure,
+    Always,
+}
+
+/// Settings of a tag step
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TagStepConfig {
+    pub input_dir: String,
+    #[serde(default)]
+    pub files: Vec<TagFile>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tags_from: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub defaults: Option<TagDefaults>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub artwork: Option<ArtworkOptions>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub album_art_from_video: Option<VideoFrameArt>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub from_filename: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub from_source: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub musicbrainz: Option<MusicBrainzSource>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub verify: Option<TagVerifyMode>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub clear_existing: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub remove: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub remove_tag_types: Option<Vec<TagFormat>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id3: Option<Id3Options>,
+}
+
+impl TagStepConfig {
+    /// Values for whatever a file has not set after every other source: the step defaults,
+    /// then the album-level metadata
+    pub fn merged_defaults(&self, album: Option<&TagDefaults>) -> Option<TagFile> {
+        match (self.defaults.as_ref().map(TagFile::from), album.map(TagFile::from)) {
+            (Some(defaults), Some(album)) => Some(defaults.merged_with(&album)),
+            (defaults, album) => defaults.or(album),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -76,17 +447,32 @@ pub enum StepConfig {
         output_dir: String,
         files: Vec<SplitFile>,
     },
+    Subtitles {
+        input: String,
+        output_dir: String,
+        #[serde(default)]
+        stream: u32,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        files: Option<Vec<SplitFile>>,
+    },
     Transcode {
         input_dir: String,
         output_dir: String,
         files: Vec<String>,
     },
-    Tag {
-        input_dir: String,
-        files: Vec<TagFile>,
-    },
+    Tag(Box<TagStepConfig>),
     Cleanup {
         files: Vec<String>,
+        /// Only remove paths created during this run
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        only_created: Option<bool>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        mode: Option<CleanupMode>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        when: Option<RunCondition>,
+        /// Fail the step when any path could not be removed
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        strict: Option<bool>,
     },
 }
 
@@ -117,6 +503,31 @@ impl Config {
     pub fn has_transcode_step(&self) -> bool {
         self.steps.iter().any(|step| matches!(step, StepConfig::Transcode { .. }))
     }
+
+    /// Segments sliced by the subtitles step at `step_index`
+    ///
+    /// Uses the step's own `files` when given, otherwise the segments of the closest
+    /// preceding split step with each file name changed to `.lrc`.
+    pub fn subtitle_segments(&self, step_index: usize) -> Option<Vec<SplitFile>> {
+        if let Some(StepConfig::Subtitles { files: Some(files), .. }) = self.steps.get(step_index) {
+            return Some(files.clone());
+        }
+
+        self.steps[..step_index.min(self.steps.len())].iter().rev().find_map(|step| match step {
+            StepConfig::Split { files, .. } => Some(
+                files.iter()
+                    .map(|file| SplitFile {
+                        file: std::path::Path::new(&file.file)
+                            .with_extension("lrc")
+                            .to_string_lossy()
+                            .into_owned(),
+                        ..file.clone()
+                    })
+                    .collect()
+            ),
+            _ => None,
+        })
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -273,6 +684,78 @@ steps: []
         // Should be a YAML parsing error
     }
 
+    #[test]
+    fn test_config_album_metadata() {
+        let yaml = r#"
+syntax: soundpipeline
+syntax_version: 1
+formats:
+  available: []
+album:
+  album: "Live at the Hall"
+  album_artist: "The Band"
+  disk_total: 2
+steps: []
+"#;
+        
+        let config: Config = serde_yaml::from_str(yaml).unwrap();
+        
+        let album = config.album.unwrap();
+        assert_eq!(album.album, Some("Live at the Hall".to_string()));
+        assert_eq!(album.album_artist, Some("The Band".to_string()));
+        assert_eq!(album.disk_total, Some(2));
+        assert_eq!(album.artist, None);
+    }
+
+    #[test]
+    fn test_subtitle_segments() {
+        let yaml = r#"
+syntax: soundpipeline
+syntax_version: 1
+formats:
+  available:
+    - format: mp3
+steps:
+  - type: split
+    input: audio.wav
+    output_dir: split
+    files:
+      - file: "01 - Intro.wav"
+        start: "0:00:00.000"
+        end: "0:03:00.000"
+  - type: subtitles
+    input: video.mkv
+    stream: 1
+    output_dir: lyrics
+  - type: subtitles
+    input: video.mkv
+    output_dir: lyrics
+    files:
+      - file: "custom.lrc"
+        start: "0:01:00.000"
+        end: "0:02:00.000"
+"#;
+
+        let config: Config = serde_yaml::from_str(yaml).unwrap();
+
+        if let StepConfig::Subtitles { stream, files, .. } = &config.steps[1] {
+            assert_eq!(*stream, 1);
+            assert!(files.is_none());
+        } else {
+            panic!("Expected Subtitles step");
+        }
+
+        let inherited = config.subtitle_segments(1).unwrap();
+        assert_eq!(inherited.len(), 1);
+        assert_eq!(inherited[0].file, "01 - Intro.lrc");
+        assert_eq!(inherited[0].end, "0:03:00.000");
+
+        let explicit = config.subtitle_segments(2).unwrap();
+        assert_eq!(explicit[0].file, "custom.lrc");
+
+        assert!(config.subtitle_segments(0).is_none());
+    }
+
     #[test]
     fn test_has_transcode_step() {
         let mut config = Config {
@@ -283,6 +766,7 @@ steps: []
                 default: None,
             },
             settings: None,
+            album: None,
             steps: vec![],
         };
 
@@ -315,12 +799,17 @@ steps: []
         assert!(config.has_transcode_step());
 
         // Add more steps - still has transcode
-        config.steps.push(StepConfig::Tag {
+        config.steps.push(StepConfig::Tag(Box::new(TagStepConfig {
             input_dir: "output".to_string(),
             files: vec![],
-        });
+            ..Default::default()
+        })));
         config.steps.push(StepConfig::Cleanup {
             files: vec!["split".to_string()],
+            only_created: None,
+            mode: None,
+            when: None,
+            strict: None,
         });
         assert!(config.has_transcode_step());
     }
@@ -496,7 +985,8 @@ files:
         
         let step: StepConfig = serde_yaml::from_str(yaml).unwrap();
         
-        if let StepConfig::Tag { input_dir, files } = step {
+        if let StepConfig::Tag(tag) = step {
+            let TagStepConfig { input_dir, files, tags_from, defaults, artwork, album_art_from_video, from_filename, from_source, musicbrainz, verify, clear_existing, remove, remove_tag_types, id3 } = *tag;
             assert_eq!(input_dir, "output");
             assert_eq!(files.len(), 1);
             let tag_file = &files[0];
@@ -507,11 +997,394 @@ files:
             assert_eq!(tag_file.track, Some(1));
             assert_eq!(tag_file.track_total, Some(2));
             assert_eq!(tag_file.album_art, Some("cover.jpg".to_string()));
+            assert!(tags_from.is_none());
+            assert!(defaults.is_none());
+            assert!(artwork.is_none());
+            assert!(album_art_from_video.is_none());
+            assert!(from_filename.is_none());
+            assert!(from_source.is_none());
+            assert!(musicbrainz.is_none());
+            assert!(verify.is_none());
+            assert!(clear_existing.is_none());
+            assert!(remove.is_none());
+            assert!(remove_tag_types.is_none());
+            assert!(id3.is_none());
         } else {
             panic!("Expected Tag step");
         }
     }
 
+    #[test]
+    fn test_tag_file_custom_deserialization() {
+        let yaml = r#"
+file: "track_01.*"
+title: "First Track"
+custom:
+  SOURCE_URL: "https://example.com/live"
+  MusicBrainz Album Id: "0b1d1a48-0000-0000-0000-000000000000"
+"#;
+        
+        let tag_file: TagFile = serde_yaml::from_str(yaml).unwrap();
+        
+        let custom = tag_file.custom.unwrap();
+        assert_eq!(custom.len(), 2);
+        assert_eq!(custom.get("SOURCE_URL"), Some(&"https://example.com/live".to_string()));
+        assert_eq!(custom.get("MusicBrainz Album Id"), Some(&"0b1d1a48-0000-0000-0000-000000000000".to_string()));
+    }
+
+    #[test]
+    fn test_step_config_tag_defaults_deserialization() {
+        let yaml = r#"
+type: tag
+input_dir: "output"
+defaults:
+  artist: "Test Artist"
+  album: "Test Album"
+  year: 2024
+  album_art: "cover.jpg"
+files:
+  - file: "track_01.*"
+    title: "First Track"
+    track: 1
+"#;
+        
+        let step: StepConfig = serde_yaml::from_str(yaml).unwrap();
+        
+        if let StepConfig::Tag(tag) = step {
+            let TagStepConfig { files, defaults, .. } = *tag;
+            assert_eq!(files.len(), 1);
+            let defaults = defaults.unwrap();
+            assert_eq!(defaults.artist, Some("Test Artist".to_string()));
+            assert_eq!(defaults.album, Some("Test Album".to_string()));
+            assert_eq!(defaults.year, Some(2024));
+            assert_eq!(defaults.album_art, Some("cover.jpg".to_string()));
+        } else {
+            panic!("Expected Tag step");
+        }
+    }
+
+    #[test]
+    fn test_step_config_tag_artwork_deserialization() {
+        let yaml = r#"
+type: tag
+input_dir: "output"
+artwork:
+  max_dimension: 1000
+  max_bytes: 500000
+  format: jpeg
+  save_as: ["cover.jpg", "folder.jpg"]
+files:
+  - file: "*.mp3"
+    album_art: "cover.png"
+"#;
+        
+        let step: StepConfig = serde_yaml::from_str(yaml).unwrap();
+        
+        if let StepConfig::Tag(tag) = step {
+            let TagStepConfig { artwork, .. } = *tag;
+            let artwork = artwork.unwrap();
+            assert_eq!(artwork.max_dimension, Some(1000));
+            assert_eq!(artwork.max_bytes, Some(500000));
+            assert_eq!(artwork.format, Some(ArtworkFormat::Jpeg));
+            assert_eq!(artwork.quality, None);
+            assert_eq!(artwork.save_as, vec!["cover.jpg", "folder.jpg"]);
+        } else {
+            panic!("Expected Tag step");
+        }
+    }
+
+    #[test]
+    fn test_step_config_tag_musicbrainz_deserialization() {
+        let yaml = r#"
+type: tag
+input_dir: "output"
+musicbrainz:
+  release: "release.json"
+files:
+  - file: "*.flac"
+    date: "2024-05-01"
+    isrc: "GBAAA2400001"
+    musicbrainz:
+      artist_ids: ["a1", "a2"]
+"#;
+
+        let step: StepConfig = serde_yaml::from_str(yaml).unwrap();
+
+        if let StepConfig::Tag(tag) = step {
+            let TagStepConfig { files, musicbrainz, .. } = *tag;
+            let musicbrainz = musicbrainz.unwrap();
+            assert_eq!(musicbrainz.release, "release.json");
+            assert_eq!(musicbrainz.medium, 1);
+            assert_eq!(files[0].date, Some("2024-05-01".to_string()));
+            assert_eq!(files[0].isrc, Some("GBAAA2400001".to_string()));
+            let ids = files[0].musicbrainz.as_ref().unwrap();
+            assert_eq!(ids.artist_ids, Some(vec!["a1".to_string(), "a2".to_string()]));
+            assert_eq!(ids.track_id, None);
+        } else {
+            panic!("Expected Tag step");
+        }
+    }
+
+    #[test]
+    fn test_step_config_tag_verify_deserialization() {
+        let yaml = r#"
+type: tag
+input_dir: "output"
+verify: fail
+files:
+  - file: "*.m4a"
+"#;
+
+        let step: StepConfig = serde_yaml::from_str(yaml).unwrap();
+
+        assert!(matches!(step, StepConfig::Tag(tag) if tag.verify == Some(TagVerifyMode::Fail)));
+        assert!(serde_yaml::from_str::<StepConfig>(&yaml.replace("fail", "always")).is_err());
+    }
+
+    #[test]
+    fn test_step_config_tag_remove_deserialization() {
+        let yaml = r#"
+type: tag
+input_dir: "output"
+remove: [comment, encoder]
+remove_tag_types: [id3v1, ape]
+files:
+  - file: "01.mp3"
+    clear_existing: true
+  - file: "02.mp3"
+    remove: [SOURCE_URL]
+"#;
+
+        let step: StepConfig = serde_yaml::from_str(yaml).unwrap();
+
+        if let StepConfig::Tag(tag) = step {
+            let TagStepConfig { files, clear_existing, remove, remove_tag_types, .. } = *tag;
+            assert_eq!(clear_existing, None);
+            assert_eq!(remove, Some(vec!["comment".to_string(), "encoder".to_string()]));
+            assert_eq!(remove_tag_types, Some(vec![TagFormat::Id3v1, TagFormat::Ape]));
+            assert_eq!(files[0].clear_existing, Some(true));
+            assert_eq!(files[1].remove, Some(vec!["SOURCE_URL".to_string()]));
+        } else {
+            panic!("Expected Tag step");
+        }
+    }
+
+    #[test]
+    fn test_step_config_tag_id3_deserialization() {
+        let yaml = r#"
+type: tag
+input_dir: "output"
+id3:
+  version: 2.3
+  encoding: utf16
+  id3v1: true
+files:
+  - file: "*.mp3"
+"#;
+
+        let step: StepConfig = serde_yaml::from_str(yaml).unwrap();
+
+        if let StepConfig::Tag(tag) = step {
+            let TagStepConfig { id3, .. } = *tag;
+            let id3 = id3.unwrap();
+            assert_eq!(id3.version, Id3Version::V23);
+            assert_eq!(id3.encoding, Some(Id3Encoding::Utf16));
+            assert!(id3.id3v1);
+        } else {
+            panic!("Expected Tag step");
+        }
+
+        let options: Id3Options = serde_yaml::from_str("version: \"2.4\"").unwrap();
+        assert_eq!(options.version, Id3Version::V24);
+        assert_eq!(options.encoding, None);
+        assert!(!options.id3v1);
+        assert_eq!(serde_yaml::to_string(&options).unwrap(), "version: '2.4'\nencoding: null\nid3v1: false\n");
+
+        assert!(serde_yaml::from_str::<Id3Options>("version: 2.2").is_err());
+        assert!(serde_yaml::from_str::<Id3Options>("encoding: utf32").is_err());
+    }
+
+    #[test]
+    fn test_tag_file_images_deserialization() {
+        let yaml = r#"
+file: "track_01.*"
+album_art: "cover.jpg"
+images:
+  - file: "back.jpg"
+    type: back_cover
+  - file: "booklet_1.jpg"
+    type: booklet
+    description: "Booklet page 1"
+  - file: "extra.png"
+"#;
+        
+        let tag_file: TagFile = serde_yaml::from_str(yaml).unwrap();
+        let pictures = tag_file.pictures();
+        
+        assert_eq!(pictures.len(), 4);
+        assert_eq!(pictures[0].file, "cover.jpg");
+        assert_eq!(pictures[0].image_type, ImageType::FrontCover);
+        assert_eq!(pictures[1].image_type, ImageType::BackCover);
+        assert_eq!(pictures[2].image_type, ImageType::Leaflet);
+        assert_eq!(pictures[2].description, Some("Booklet page 1".to_string()));
+        assert_eq!(pictures[3].image_type, ImageType::FrontCover);
+        
+        let defaults = TagDefaults {
+            images: Some(vec![TagImage {
+                file: "disc.jpg".to_string(),
+                image_type: ImageType::Media,
+                description: None,
+            }]),
+            ..Default::default()
+        };
+        let resolved = TagFile { file: "track_02.*".to_string(), ..Default::default() }.merged_with(&TagFile::from(&defaults));
+        assert_eq!(resolved.pictures().len(), 1);
+        assert_eq!(resolved.pictures()[0].image_type, ImageType::Media);
+    }
+
+    #[test]
+    fn test_video_frame_art() {
+        let yaml = r#"
+type: tag
+input_dir: "output"
+album_art_from_video:
+  input: "concert.mkv"
+  timestamp: "0:01:23.500"
+files:
+  - file: "track_01.*"
+  - file: "track_02.*"
+    album_art: "special.jpg"
+"#;
+        
+        let step: StepConfig = serde_yaml::from_str(yaml).unwrap();
+        
+        if let StepConfig::Tag(tag) = step {
+            let TagStepConfig { mut files, album_art_from_video, .. } = *tag;
+            let frame = album_art_from_video.unwrap();
+            assert_eq!(frame.output, "cover.jpg");
+            assert!(frame.crop.is_none());
+            
+            frame.apply_to(&mut files);
+            assert_eq!(files[0].album_art, Some("cover.jpg".to_string()));
+            assert_eq!(files[1].album_art, Some("special.jpg".to_string()));
+        } else {
+            panic!("Expected Tag step");
+        }
+    }
+
+    #[test]
+    fn test_tag_file_merged_with() {
+        let explicit = TagFile {
+            file: "*.wav".to_string(),
+            title: Some("Explicit Title".to_string()),
+            custom: Some(BTreeMap::from([("MOOD".to_string(), "calm".to_string())])),
+            ..Default::default()
+        };
+        let parsed = TagFile {
+            file: "03 - Artist - Title.wav".to_string(),
+            title: Some("Title".to_string()),
+            artist: Some("Artist".to_string()),
+            track: Some(3),
+            custom: Some(BTreeMap::from([("SOURCE".to_string(), "file".to_string())])),
+            ..Default::default()
+        };
+        
+        let merged = explicit.merged_with(&parsed);
+        
+        assert_eq!(merged.file, "*.wav");
+        assert_eq!(merged.title, Some("Explicit Title".to_string()));
+        assert_eq!(merged.artist, Some("Artist".to_string()));
+        assert_eq!(merged.track, Some(3));
+        assert_eq!(merged.custom.unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_tag_step_merged_defaults() {
+        let step = TagStepConfig {
+            defaults: Some(TagDefaults {
+                artist: Some("Step Artist".to_string()),
+                genre: Some("Pop".to_string()),
+                custom: Some(BTreeMap::from([
+                    ("MOOD".to_string(), "upbeat".to_string()),
+                    ("SOURCE".to_string(), "Blu-ray".to_string()),
+                ])),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let album = TagDefaults {
+            artist: Some("Album Artist".to_string()),
+            album: Some("Live Album".to_string()),
+            year: Some(2024),
+            ..Default::default()
+        };
+        let file = TagFile {
+            file: "track_01.*".to_string(),
+            title: Some("First Track".to_string()),
+            genre: Some("Rock".to_string()),
+            custom: Some(BTreeMap::from([("MOOD".to_string(), "calm".to_string())])),
+            ..Default::default()
+        };
+        
+        let defaults = step.merged_defaults(Some(&album)).unwrap();
+        let resolved = file.merged_with(&defaults);
+        
+        // Step defaults win over album metadata, and only fill what the file leaves unset
+        assert_eq!(resolved.file, "track_01.*");
+        assert_eq!(resolved.title, Some("First Track".to_string()));
+        assert_eq!(resolved.artist, Some("Step Artist".to_string()));
+        assert_eq!(resolved.genre, Some("Rock".to_string()));
+        assert_eq!(resolved.album, Some("Live Album".to_string()));
+        assert_eq!(resolved.year, Some(2024));
+        
+        // Custom maps are merged key by key
+        let custom = resolved.custom.as_ref().unwrap();
+        assert_eq!(custom.get("MOOD"), Some(&"calm".to_string()));
+        assert_eq!(custom.get("SOURCE"), Some(&"Blu-ray".to_string()));
+        
+        assert_eq!(TagStepConfig::default().merged_defaults(Some(&album)).unwrap().artist, Some("Album Artist".to_string()));
+        assert!(TagStepConfig::default().merged_defaults(None).is_none());
+    }
+
+    #[test]
+    fn test_fill_track_totals() {
+        let mut files = vec![
+            TagFile { file: "d1_01.flac".to_string(), track: Some(1), disk: Some(1), ..Default::default() },
+            TagFile { file: "d1_02.flac".to_string(), track: Some(2), disk: Some(1), ..Default::default() },
+            TagFile { file: "d1_03.flac".to_string(), track: Some(3), disk: Some(1), ..Default::default() },
+            TagFile { file: "d2_01.flac".to_string(), track: Some(1), disk: Some(2), ..Default::default() },
+            TagFile { file: "d2_02.flac".to_string(), track: Some(2), disk: Some(2), track_total: Some(9), ..Default::default() },
+            TagFile { file: "bonus.flac".to_string(), disk: Some(2), ..Default::default() },
+        ];
+        
+        fill_track_totals(&mut files);
+        
+        assert_eq!(files[0].track_total, Some(3));
+        assert_eq!(files[2].track_total, Some(3));
+        // bonus.flac has no track number, so disk 2 has two tracks
+        assert_eq!(files[3].track_total, Some(2));
+        // Explicit values are kept
+        assert_eq!(files[4].track_total, Some(9));
+        // Files without a track number are left alone
+        assert_eq!(files[5].track_total, None);
+        
+        // A glob entry matching the same files again does not count them twice
+        let track = |file: &str, track: u32| TagFile { file: file.to_string(), track: Some(track), ..Default::default() };
+        let comment = |file: &str| TagFile { file: file.to_string(), comment: Some("Live".to_string()), ..Default::default() };
+        let mut resolved = [track("01.mp3", 1), track("02.mp3", 2), comment("01.mp3"), comment("02.mp3")];
+        fill_track_totals(&mut resolved);
+        assert_eq!(resolved[0].track_total, Some(2));
+        assert_eq!(resolved[1].track_total, Some(2));
+        assert_eq!(resolved[2].track_total, None);
+        
+        // A track total from the defaults disables the automatic count
+        let defaults = TagDefaults { track_total: Some(12), ..Default::default() };
+        let file = TagFile { file: "01.flac".to_string(), track: Some(1), ..Default::default() };
+        let mut resolved = [file.merged_with(&TagFile::from(&defaults))];
+        fill_track_totals(&mut resolved);
+        assert_eq!(resolved[0].track_total, Some(12));
+    }
+
     #[test]
     fn test_step_config_cleanup_deserialization() {
         let yaml = r#"
@@ -521,13 +1394,42 @@ files: ["split", "audio.wav", "temp"]
         
         let step: StepConfig = serde_yaml::from_str(yaml).unwrap();
         
-        if let StepConfig::Cleanup { files } = step {
+        if let StepConfig::Cleanup { files, only_created, mode, when, strict } = step {
             assert_eq!(files, vec!["split", "audio.wav", "temp"]);
+            assert_eq!(only_created, None);
+            assert_eq!(mode, None);
+            assert_eq!(when, None);
+            assert_eq!(strict, None);
         } else {
             panic!("Expected Cleanup step");
         }
     }
 
+    #[test]
+    fn test_step_config_cleanup_options_deserialization() {
+        let yaml = r#"
+type: cleanup
+mode: trash
+only_created: true
+when: always
+strict: true
+files: ["split"]
+"#;
+        
+        let step: StepConfig = serde_yaml::from_str(yaml).unwrap();
+        
+        if let StepConfig::Cleanup { only_created, mode, when, strict, .. } = step {
+            assert_eq!(strict, Some(true));
+            assert_eq!(only_created, Some(true));
+            assert_eq!(mode, Some(CleanupMode::Trash));
+            assert_eq!(when, Some(RunCondition::Always));
+        } else {
+            panic!("Expected Cleanup step");
+        }
+        
+        assert!(serde_yaml::from_str::<StepConfig>("type: cleanup\nmode: shred\nfiles: []").is_err());
+    }
+
     #[test]
     fn test_split_file_skipped_fields() {
         let split_file = SplitFile {
@@ -564,6 +1466,7 @@ files: ["split", "audio.wav", "temp"]
             genre: None,
             year: None,
             comment: None,
+            ..Default::default()
         };
         
         let yaml = serde_yaml::to_string(&tag_file).unwrap();
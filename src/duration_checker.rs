@@ -24,6 +24,12 @@ pub struct DurationCheckResult {
     pub checks: Vec<DurationCheckInfo>,
 }
 
+impl Default for DurationCheckResult {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl DurationCheckResult {
     pub fn new() -> Self {
         Self {
@@ -1,14 +1,19 @@
 use anyhow::Result;
 use ffmpeg_sidecar::download::{ffmpeg_download_url, unpack_ffmpeg};
 use ffmpeg_sidecar::paths::{sidecar_dir, ffmpeg_path};
-use ffmpeg_sidecar::command::ffmpeg_is_installed;
+use ffmpeg_sidecar::command::{ffmpeg_is_installed, FfmpegCommand};
+use ffmpeg_sidecar::event::{FfmpegEvent, LogLevel};
 use ffmpeg_sidecar::ffprobe::ffprobe_path;
+use crate::cancel;
+use crate::progress::FileProgress;
 use indicatif::{ProgressBar, ProgressStyle};
 use std::fs::File;
 use std::io::BufReader;
 use std::path::{Path, PathBuf};
 use std::process::Command;
-use tracing::debug;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tracing::{debug, warn};
 
 /// Auto-download FFmpeg with progress tracking
 pub fn auto_download_with_progress() -> Result<()> {
@@ -24,7 +29,7 @@ pub fn auto_download_with_progress() -> Result<()> {
     let destination = sidecar_dir()?;
     
     // Download with progress
-    let archive_path = download_ffmpeg_package_with_progress(&download_url, &destination)?;
+    let archive_path = download_ffmpeg_package_with_progress(download_url, &destination)?;
     
     // Unpack
     tracing::info!("Unpacking FFmpeg...");
@@ -56,7 +61,7 @@ fn download_ffmpeg_package_with_progress(url: &str, destination: &Path) -> Resul
     // Extract filename from URL
     let filename = url
         .split('/')
-        .last()
+        .next_back()
         .unwrap_or("ffmpeg.zip");
     
     let file_path = destination.join(filename);
@@ -213,6 +218,233 @@ pub fn check_encoder_availability() -> Result<EncoderAvailability> {
     })
 }
 
+/// Error and warning lines kept for the message of a failed command
+const ERROR_LINES: usize = 10;
+
+/// Runs the FFmpeg commands of the steps, optionally keeping a log file of each
+#[derive(Debug, Clone, Default)]
+pub struct FfmpegRunner {
+    log_dir: Option<PathBuf>,
+    /// Numbers the log files in the order the commands ran
+    runs: Arc<AtomicUsize>,
+}
+
+impl FfmpegRunner {
+    /// `log_dir` receives the full output of every command when set
+    pub fn new(log_dir: Option<PathBuf>) -> Self {
+        Self { log_dir, runs: Arc::default() }
+    }
+
+    /// Run a command writing `output` to completion, passing on its progress. A failure carries
+    /// the last errors and warnings FFmpeg logged. The process is killed when the run is interrupted.
+    pub fn run(
+        &self,
+        command: &mut FfmpegCommand,
+        description: &str,
+        output: &Path,
+        mut on_progress: impl FnMut(&FileProgress),
+    ) -> Result<()> {
+        let command_line = command_line(command);
+        debug!("FFmpeg command: {}", command_line);
+
+        let mut child = command.spawn()?;
+        let mut tracker = ProgressTracker::new();
+        let mut log = FfmpegLog::default();
+        for event in child.iter()? {
+            if cancel::is_cancelled() {
+                child.kill()?;
+                child.wait()?;
+                anyhow::bail!("Interrupted while running FFmpeg to {}", description);
+            }
+            if let Some(progress) = tracker.update(&event) {
+                on_progress(&progress);
+            }
+            log.record(&event);
+        }
+        let status = child.wait()?;
+
+        let log_path = self.write_log(output, &command_line, &log);
+        // A Ctrl-C in the terminal reaches FFmpeg too, which then stops on its own
+        cancel::check()?;
+        if status.success() {
+            return Ok(());
+        }
+
+        let mut message = match status.code() {
+            Some(code) => format!("FFmpeg failed to {} (exit code {})", description, code),
+            None => format!("FFmpeg failed to {} (terminated by a signal)", description),
+        };
+        let problems = log.last_problems(ERROR_LINES);
+        if !problems.is_empty() {
+            message.push(':');
+            for line in problems {
+                message.push_str("\n  ");
+                message.push_str(line);
+            }
+        }
+        if let Some(path) = log_path {
+            message.push_str(&format!("\nFull log: {}", path.display()));
+        }
+        anyhow::bail!(message)
+    }
+
+    /// Write the log of a command to the log directory, returning where it went
+    fn write_log(&self, output: &Path, command_line: &str, log: &FfmpegLog) -> Option<PathBuf> {
+        let log_dir = self.log_dir.as_ref()?;
+        let run = self.runs.fetch_add(1, Ordering::Relaxed) + 1;
+        let name = output.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+        let path = log_dir.join(format!("{:03}-{}.log", run, name));
+
+        let contents = format!("{}\n\n{}", command_line, log.lines.join("\n"));
+        match std::fs::create_dir_all(log_dir).and_then(|_| std::fs::write(&path, contents)) {
+            Ok(()) => {
+                debug!("Wrote FFmpeg log: {}", path.display());
+                Some(path)
+            }
+            Err(e) => {
+                warn!("Failed to write FFmpeg log {}: {}", path.display(), e);
+                None
+            }
+        }
+    }
+}
+
+/// What FFmpeg logged during a command
+#[derive(Debug, Default)]
+pub struct FfmpegLog {
+    lines: Vec<String>,
+    /// Indices into `lines` of errors and warnings
+    problems: Vec<usize>,
+}
+
+impl FfmpegLog {
+    pub fn record(&mut self, event: &FfmpegEvent) {
+        let (line, problem) = match event {
+            FfmpegEvent::Log(level, msg) => {
+                let problem = matches!(level, LogLevel::Warning | LogLevel::Error | LogLevel::Fatal);
+                (msg.clone(), problem)
+            }
+            FfmpegEvent::Error(msg) => (format!("[error] {}", msg), true),
+            FfmpegEvent::ParsedVersion(version) => (version.raw_log_message.clone(), false),
+            FfmpegEvent::ParsedConfiguration(configuration) => (configuration.raw_log_message.clone(), false),
+            FfmpegEvent::ParsedStreamMapping(msg) => (msg.clone(), false),
+            FfmpegEvent::ParsedInput(input) => (input.raw_log_message.clone(), false),
+            FfmpegEvent::ParsedOutput(output) => (output.raw_log_message.clone(), false),
+            FfmpegEvent::ParsedInputStream(stream) | FfmpegEvent::ParsedOutputStream(stream) => (stream.raw_log_message.clone(), false),
+            FfmpegEvent::ParsedDuration(duration) => (duration.raw_log_message.clone(), false),
+            FfmpegEvent::Progress(progress) => (progress.raw_log_message.clone(), false),
+            _ => return,
+        };
+        if problem {
+            debug!("FFmpeg: {}", line);
+            self.problems.push(self.lines.len());
+        }
+        self.lines.push(line);
+    }
+
+    /// The last `count` error and warning lines, oldest first
+    pub fn last_problems(&self, count: usize) -> Vec<&str> {
+        let skip = self.problems.len().saturating_sub(count);
+        self.problems[skip..].iter().map(|&i| self.lines[i].as_str()).collect()
+    }
+}
+
+/// The command as it could be pasted into a shell, e.g. `ffmpeg -i 'my file.mkv' out.wav`
+pub fn command_line(command: &FfmpegCommand) -> String {
+    std::iter::once("ffmpeg".to_string())
+        .chain(command.get_args().map(|arg| shell_quote(&arg.to_string_lossy())))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn shell_quote(arg: &str) -> String {
+    let plain = |c: char| c.is_ascii_alphanumeric() || "-_./:=+,@%".contains(c);
+    if !arg.is_empty() && arg.chars().all(plain) {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace('\'', "'\\''"))
+    }
+}
+
+/// Follows an FFmpeg run through its events to tell how far it is
+#[derive(Debug, Default)]
+pub struct ProgressTracker {
+    total_seconds: Option<f64>,
+}
+
+impl ProgressTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Progress after this event, if it says anything about progress
+    pub fn update(&mut self, event: &FfmpegEvent) -> Option<FileProgress> {
+        match event {
+            FfmpegEvent::ParsedDuration(duration) => {
+                debug!("Total duration: {:.2} seconds", duration.duration);
+                self.total_seconds = Some(duration.duration);
+                None
+            }
+            FfmpegEvent::Progress(progress) => {
+                let Some(time) = parse_time(&progress.time) else {
+                    debug!("Failed to parse time: {}", progress.time);
+                    return None;
+                };
+                Some(FileProgress::from_ffmpeg(time, Some(progress.speed as f64), self.total_seconds))
+            }
+            // Progress in log messages like:
+            // "size=  685824KiB time=00:40:38.68 bitrate=2303.8kbits/s speed= 254x"
+            FfmpegEvent::Log(LogLevel::Info, msg) if msg.contains("time=") && msg.contains("speed=") => {
+                let time = extract_time_from_log(msg).and_then(|time| parse_time(&time))?;
+                let speed = extract_speed_from_log(msg)
+                    .and_then(|speed| speed.trim_end_matches('x').parse().ok());
+                Some(FileProgress::from_ffmpeg(time, speed, self.total_seconds))
+            }
+            _ => None,
+        }
+    }
+}
+
+// Parse FFmpeg time format "hh:mm:ss.ff" to seconds
+fn parse_time(time_str: &str) -> Option<f64> {
+    let parts: Vec<&str> = time_str.split(':').collect();
+    if parts.len() != 3 {
+        return None;
+    }
+    
+    let hours: f64 = parts[0].parse().ok()?;
+    let minutes: f64 = parts[1].parse().ok()?;
+    let seconds: f64 = parts[2].parse().ok()?;
+    
+    Some(hours * 3600.0 + minutes * 60.0 + seconds)
+}
+
+// Extract time from log message like "size=  685824KiB time=00:40:38.68 bitrate=2303.8kbits/s speed= 254x"
+fn extract_time_from_log(log_msg: &str) -> Option<String> {
+    if let Some(time_start) = log_msg.find("time=") {
+        let time_part = &log_msg[time_start + 5..];
+        if let Some(time_end) = time_part.find(' ') {
+            return Some(time_part[..time_end].to_string());
+        }
+    }
+    None
+}
+
+// Extract speed from log message like "speed= 254x"
+fn extract_speed_from_log(log_msg: &str) -> Option<String> {
+    if let Some(speed_start) = log_msg.find("speed=") {
+        let speed_part = &log_msg[speed_start + 6..].trim_start();
+        // Find the end by looking for the closing ']' or '"' since the log message ends there
+        if let Some(speed_end) = speed_part.find('"').or_else(|| speed_part.find(']')) {
+            return Some(speed_part[..speed_end].trim().to_string());
+        } else {
+            // If no end delimiter, take the whole remaining part
+            return Some(speed_part.trim().to_string());
+        }
+    }
+    None
+}
+
 #[derive(Debug, Clone)]
 pub struct EncoderAvailability {
     pub aac_at: bool,
@@ -261,4 +493,68 @@ mod tests {
         assert!(parse_ffmpeg_duration("00:60:00").is_err()); // 60 minutes
         assert!(parse_ffmpeg_duration("00:00:60").is_err()); // 60 seconds
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_shell_quote() {
+        assert_eq!(shell_quote("-c:a"), "-c:a");
+        assert_eq!(shell_quote("splits/track_01.wav"), "splits/track_01.wav");
+        assert_eq!(shell_quote("my file.mkv"), "'my file.mkv'");
+        assert_eq!(shell_quote("it's"), "'it'\\''s'");
+        assert_eq!(shell_quote(""), "''");
+    }
+
+    #[test]
+    fn test_progress_tracker() {
+        let mut tracker = ProgressTracker::new();
+        let log = |msg: &str| FfmpegEvent::Log(LogLevel::Info, msg.to_string());
+
+        let progress = tracker.update(&log("size=  1024KiB time=00:01:00.00 bitrate=2303.8kbits/s speed=  20x")).unwrap();
+        assert_eq!(progress.time_seconds, 60.0);
+        assert_eq!(progress.speed, Some(20.0));
+        assert_eq!(progress.percent, None);
+
+        tracker.update(&FfmpegEvent::ParsedDuration(ffmpeg_sidecar::event::FfmpegDuration {
+            input_index: 0,
+            duration: 240.0,
+            raw_log_message: String::new(),
+        }));
+        let progress = tracker.update(&log("size=  1024KiB time=00:01:00.00 bitrate=2303.8kbits/s speed=  20x")).unwrap();
+        assert_eq!(progress.percent, Some(25.0));
+        assert_eq!(progress.eta_seconds, Some(9.0));
+
+        assert_eq!(tracker.update(&log("Stream mapping:")), None);
+    }
+
+    #[test]
+    fn test_ffmpeg_log() {
+        let mut log = FfmpegLog::default();
+        log.record(&FfmpegEvent::Log(LogLevel::Info, "[info] Stream #0:0: Audio: flac".to_string()));
+        log.record(&FfmpegEvent::Log(LogLevel::Warning, "[warning] Guessed Channel Layout: stereo".to_string()));
+        log.record(&FfmpegEvent::Log(LogLevel::Error, "[error] Unknown encoder 'libfoo'".to_string()));
+        log.record(&FfmpegEvent::Error("broken pipe".to_string()));
+        log.record(&FfmpegEvent::LogEOF);
+
+        assert_eq!(log.lines.len(), 4);
+        assert_eq!(log.last_problems(2), ["[error] Unknown encoder 'libfoo'", "[error] broken pipe"]);
+        assert_eq!(log.last_problems(10).len(), 3);
+    }
+
+    #[test]
+    fn test_write_log() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let runner = FfmpegRunner::new(Some(dir.path().join("logs")));
+        let mut log = FfmpegLog::default();
+        log.record(&FfmpegEvent::Log(LogLevel::Error, "[error] Unknown encoder 'libfoo'".to_string()));
+
+        let first = runner.write_log(Path::new("/work/splits/01.wav"), "ffmpeg -i in.mkv 01.wav", &log).unwrap();
+        let second = runner.clone().write_log(Path::new("/work/out/01.flac"), "ffmpeg -i 01.wav 01.flac", &log).unwrap();
+
+        assert_eq!(first, dir.path().join("logs/001-01.wav.log"));
+        assert_eq!(second, dir.path().join("logs/002-01.flac.log"));
+        assert_eq!(
+            std::fs::read_to_string(first).unwrap(),
+            "ffmpeg -i in.mkv 01.wav\n\n[error] Unknown encoder 'libfoo'"
+        );
+        assert_eq!(FfmpegRunner::default().write_log(Path::new("01.wav"), "ffmpeg", &log), None);
+    }
+}
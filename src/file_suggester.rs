@@ -53,7 +53,7 @@ pub fn scan_files_by_pattern(working_dir: &Path, pattern: &str) -> Result<Vec<(P
 }
 
 /// Format duration in seconds to h:mm:ss.SSS format
-fn format_duration(seconds: f64) -> String {
+pub fn format_duration(seconds: f64) -> String {
     let total_seconds = seconds as u64;
     let milliseconds = ((seconds - total_seconds as f64) * 1000.0).round() as u32;
     let hours = total_seconds / 3600;
@@ -0,0 +1,195 @@
+use crate::config::TagFile;
+use anyhow::Result;
+
+/// Tag fields that can be used as `{field}` placeholders
+const TEXT_FIELDS: &[&str] = &["title", "artist", "album", "album_artist", "genre", "comment"];
+const NUMBER_FIELDS: &[&str] = &["track", "track_total", "disk", "disk_total", "year"];
+
+/// Placeholder that matches text without storing it
+const IGNORE: &str = "_";
+
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Literal(String),
+    Field(String),
+}
+
+/// A pattern such as `{track} - {artist} - {title}` matched against file names
+///
+/// The pattern is matched against the file name without its extension. Each
+/// placeholder matches as little text as possible, except the last one which takes
+/// the rest of the name, and number fields only match digits.
+#[derive(Debug, Clone)]
+pub struct FilenamePattern {
+    pattern: String,
+    segments: Vec<Segment>,
+}
+
+impl FilenamePattern {
+    pub fn parse(pattern: &str) -> Result<Self> {
+        let mut segments = Vec::new();
+        let mut rest = pattern;
+
+        while !rest.is_empty() {
+            if let Some(after_brace) = rest.strip_prefix('{') {
+                let end = after_brace.find('}')
+                    .ok_or_else(|| anyhow::anyhow!("Unclosed '{{' in filename pattern '{}'", pattern))?;
+                let field = &after_brace[..end];
+
+                if field != IGNORE && !TEXT_FIELDS.contains(&field) && !NUMBER_FIELDS.contains(&field) {
+                    anyhow::bail!(
+                        "Unknown field '{{{}}}' in filename pattern '{}'. Expected one of: {}, {} or {}",
+                        field, pattern, TEXT_FIELDS.join(", "), NUMBER_FIELDS.join(", "), IGNORE
+                    );
+                }
+                if matches!(segments.last(), Some(Segment::Field(_))) {
+                    anyhow::bail!("Fields must be separated by text in filename pattern '{}'", pattern);
+                }
+
+                segments.push(Segment::Field(field.to_string()));
+                rest = &after_brace[end + 1..];
+            } else {
+                let end = rest.find('{').unwrap_or(rest.len());
+                if rest[..end].contains('}') {
+                    anyhow::bail!("Unmatched '}}' in filename pattern '{}'", pattern);
+                }
+                segments.push(Segment::Literal(rest[..end].to_string()));
+                rest = &rest[end..];
+            }
+        }
+
+        if !segments.iter().any(|segment| matches!(segment, Segment::Field(_))) {
+            anyhow::bail!("Filename pattern '{}' has no fields", pattern);
+        }
+
+        Ok(Self {
+            pattern: pattern.to_string(),
+            segments,
+        })
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.pattern
+    }
+
+    /// Extract the tag fields from a file name, or `None` if it does not match
+    pub fn extract(&self, file_name: &str) -> Option<TagFile> {
+        let stem = std::path::Path::new(file_name)
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .unwrap_or(file_name);
+
+        let mut values = Vec::new();
+        if !match_segments(&self.segments, stem, &mut values) {
+            return None;
+        }
+
+        let mut tag_file = TagFile {
+            file: file_name.to_string(),
+            ..Default::default()
+        };
+        for (field, value) in values {
+            let value = value.trim().to_string();
+            let number = || value.parse::<u32>().ok();
+            match field {
+                "title" => tag_file.title = Some(value),
+                "artist" => tag_file.artist = Some(value),
+                "album" => tag_file.album = Some(value),
+                "album_artist" => tag_file.album_artist = Some(value),
+                "genre" => tag_file.genre = Some(value),
+                "comment" => tag_file.comment = Some(value),
+                "track" => tag_file.track = number(),
+                "track_total" => tag_file.track_total = number(),
+                "disk" => tag_file.disk = number(),
+                "disk_total" => tag_file.disk_total = number(),
+                "year" => tag_file.year = number(),
+                _ => {}
+            }
+        }
+
+        Some(tag_file)
+    }
+}
+
+/// Match segments against the input, trying the shortest text for each field first
+fn match_segments<'a>(segments: &'a [Segment], input: &'a str, values: &mut Vec<(&'a str, &'a str)>) -> bool {
+    match segments.split_first() {
+        None => input.is_empty(),
+        Some((Segment::Literal(literal), rest)) => {
+            input.strip_prefix(literal.as_str())
+                .is_some_and(|remaining| match_segments(rest, remaining, values))
+        }
+        Some((Segment::Field(field), rest)) => {
+            let is_number = NUMBER_FIELDS.contains(&field.as_str());
+
+            for (end, _) in input.char_indices().skip(1).chain([(input.len(), ' ')]) {
+                let value = &input[..end];
+                if value.trim().is_empty() {
+                    continue;
+                }
+                if is_number && !value.trim().chars().all(|c| c.is_ascii_digit()) {
+                    break;
+                }
+
+                values.push((field.as_str(), value));
+                if match_segments(rest, &input[end..], values) {
+                    return true;
+                }
+                values.pop();
+            }
+
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract_fields() {
+        let pattern = FilenamePattern::parse("{track} - {artist} - {title}").unwrap();
+
+        let tags = pattern.extract("03 - Artist - Title, Part 1.wav").unwrap();
+        assert_eq!(tags.track, Some(3));
+        assert_eq!(tags.artist, Some("Artist".to_string()));
+        assert_eq!(tags.title, Some("Title, Part 1".to_string()));
+
+        // The last field takes the rest of the name
+        let tags = pattern.extract("04 - Artist - Title - Live.flac").unwrap();
+        assert_eq!(tags.title, Some("Title - Live".to_string()));
+    }
+
+    #[test]
+    fn test_extract_number_fields_only_match_digits() {
+        let pattern = FilenamePattern::parse("{disk}-{track} {title}").unwrap();
+
+        let tags = pattern.extract("1-07 Song Name.mp3").unwrap();
+        assert_eq!(tags.disk, Some(1));
+        assert_eq!(tags.track, Some(7));
+        assert_eq!(tags.title, Some("Song Name".to_string()));
+
+        assert!(pattern.extract("A-07 Song Name.mp3").is_none());
+    }
+
+    #[test]
+    fn test_extract_ignore_and_mismatch() {
+        let pattern = FilenamePattern::parse("[{_}] {title}").unwrap();
+
+        let tags = pattern.extract("[live] Encore.wav").unwrap();
+        assert_eq!(tags.title, Some("Encore".to_string()));
+        assert_eq!(tags.artist, None);
+
+        assert!(pattern.extract("Encore.wav").is_none());
+    }
+
+    #[test]
+    fn test_parse_invalid_patterns() {
+        assert!(FilenamePattern::parse("{track} - {name}").is_err());
+        assert!(FilenamePattern::parse("{track}{title}").is_err());
+        assert!(FilenamePattern::parse("{track - {title}").is_err());
+        assert!(FilenamePattern::parse("track} - {title}").is_err());
+        assert!(FilenamePattern::parse("no fields").is_err());
+    }
+}
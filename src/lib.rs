@@ -6,4 +6,17 @@ pub mod validator;
 pub mod duration_checker;
 pub mod file_suggester;
 pub mod settings;
-pub mod ffmpeg;
\ No newline at end of file
+pub mod ffmpeg;
+pub mod lyrics;
+pub mod album_art;
+pub mod tag_import;
+pub mod filename_pattern;
+pub mod source_metadata;
+pub mod musicbrainz;
+pub mod tag_verify;
+pub mod tag_dump;
+pub mod workspace;
+pub mod trash;
+pub mod run_report;
+pub mod progress;
+pub mod cancel;
\ No newline at end of file
@@ -0,0 +1,443 @@
+use anyhow::Result;
+use std::path::Path;
+
+/// A single timed line of an LRC file
+#[derive(Debug, Clone, PartialEq)]
+pub struct LrcLine {
+    pub time_ms: u32,
+    pub text: String,
+}
+
+/// Lyrics loaded from inline text or a .txt/.lrc file
+#[derive(Debug, Clone)]
+pub struct Lyrics {
+    /// The lyrics exactly as given
+    pub raw: String,
+    /// Timed lines, present when the lyrics are in LRC format
+    pub lines: Option<Vec<LrcLine>>,
+}
+
+impl Lyrics {
+    /// Parse lyrics text, detecting LRC content by its timestamps
+    pub fn parse(raw: &str) -> Result<Self> {
+        let lines = if looks_like_lrc(raw) {
+            Some(parse_lrc(raw)?)
+        } else {
+            None
+        };
+
+        Ok(Self {
+            raw: raw.to_string(),
+            lines,
+        })
+    }
+
+    /// Lyrics without timestamps or LRC metadata tags
+    pub fn plain_text(&self) -> String {
+        match &self.lines {
+            Some(lines) => lines.iter()
+                .map(|line| line.text.as_str())
+                .collect::<Vec<_>>()
+                .join("\n"),
+            None => self.raw.trim().to_string(),
+        }
+    }
+
+    /// Whether there is no text to write, such as an LRC file with only metadata tags
+    pub fn is_empty(&self) -> bool {
+        self.plain_text().trim().is_empty()
+    }
+
+    /// The latest timestamp in milliseconds, if the lyrics are synchronized
+    pub fn last_timestamp_ms(&self) -> Option<u32> {
+        self.lines.as_ref()
+            .and_then(|lines| lines.iter().map(|line| line.time_ms).max())
+    }
+}
+
+/// Whether a lyrics value refers to a file rather than inline text
+pub fn is_lyrics_path(value: &str) -> bool {
+    if value.contains('\n') {
+        return false;
+    }
+
+    let lower = value.trim().to_ascii_lowercase();
+    lower.ends_with(".lrc") || lower.ends_with(".txt")
+}
+
+/// Load lyrics from a `.txt`/`.lrc` path or from inline text
+pub fn load_lyrics(value: &str) -> Result<Lyrics> {
+    if is_lyrics_path(value) {
+        let path = Path::new(value.trim());
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("Failed to read lyrics file '{}': {}", path.display(), e))?;
+        Lyrics::parse(&content)
+            .map_err(|e| anyhow::anyhow!("Invalid lyrics file '{}': {}", path.display(), e))
+    } else {
+        Lyrics::parse(value)
+    }
+}
+
+/// Check whether any line starts with an LRC timestamp like `[01:23.45]`
+fn looks_like_lrc(content: &str) -> bool {
+    content.lines().any(|line| {
+        let line = line.trim_start();
+        line.starts_with('[') && line[1..].starts_with(|c: char| c.is_ascii_digit())
+    })
+}
+
+/// Parse LRC content into timed lines sorted by time
+///
+/// Supports multiple timestamps per line and the `[offset:]` tag. Other metadata
+/// tags such as `[ar:]` or `[ti:]` are ignored.
+pub fn parse_lrc(content: &str) -> Result<Vec<LrcLine>> {
+    let mut lines = Vec::new();
+    let mut offset_ms: i64 = 0;
+
+    for (line_number, line) in content.lines().enumerate() {
+        let mut rest = line.trim();
+        let mut timestamps = Vec::new();
+
+        while let Some(stripped) = rest.strip_prefix('[') {
+            let Some(end) = stripped.find(']') else {
+                break;
+            };
+            let tag = &stripped[..end];
+            rest = &stripped[end + 1..];
+
+            if tag.starts_with(|c: char| c.is_ascii_digit()) {
+                let time_ms = parse_lrc_timestamp(tag)
+                    .ok_or_else(|| anyhow::anyhow!("Invalid LRC timestamp '[{}]' on line {}", tag, line_number + 1))?;
+                timestamps.push(time_ms);
+            } else if let Some(value) = tag.strip_prefix("offset:") {
+                offset_ms = value.trim().parse()
+                    .map_err(|_| anyhow::anyhow!("Invalid LRC offset '{}' on line {}", value, line_number + 1))?;
+            }
+        }
+
+        for time_ms in timestamps {
+            lines.push((time_ms, rest.trim().to_string()));
+        }
+    }
+
+    // A positive offset shows lyrics earlier
+    let mut lines: Vec<LrcLine> = lines.into_iter()
+        .map(|(time_ms, text)| LrcLine {
+            time_ms: (time_ms as i64 - offset_ms).max(0) as u32,
+            text,
+        })
+        .collect();
+    lines.sort_by_key(|line| line.time_ms);
+
+    Ok(lines)
+}
+
+/// Parse an LRC timestamp body (`mm:ss`, `mm:ss.xx`, `mm:ss.xxx` or `mm:ss:xx`) to milliseconds
+fn parse_lrc_timestamp(timestamp: &str) -> Option<u32> {
+    let (minutes, rest) = timestamp.split_once(':')?;
+    let minutes: u32 = minutes.parse().ok()?;
+
+    let (seconds, fraction) = match rest.split_once(['.', ':']) {
+        Some((seconds, fraction)) => (seconds, Some(fraction)),
+        None => (rest, None),
+    };
+
+    if seconds.len() != 2 {
+        return None;
+    }
+    let seconds: u32 = seconds.parse().ok()?;
+    if seconds >= 60 {
+        return None;
+    }
+
+    let millis = match fraction {
+        Some(fraction) if !fraction.is_empty() && fraction.len() <= 3 => {
+            let value: u32 = fraction.parse().ok()?;
+            value * 10u32.pow(3 - fraction.len() as u32)
+        }
+        Some(_) => return None,
+        None => 0,
+    };
+
+    Some((minutes * 60 + seconds) * 1000 + millis)
+}
+
+/// A single subtitle cue with its display interval
+#[derive(Debug, Clone, PartialEq)]
+pub struct SubtitleCue {
+    pub start_ms: u32,
+    pub end_ms: u32,
+    pub text: String,
+}
+
+/// Parse SRT subtitles into cues sorted by start time
+///
+/// Multi-line cues are joined with spaces, and formatting such as `<i>` tags or
+/// ASS override blocks (`{\k20}`) is removed.
+pub fn parse_srt(content: &str) -> Result<Vec<SubtitleCue>> {
+    let mut cues = Vec::new();
+    let content = content.trim_start_matches('\u{feff}').replace("\r\n", "\n");
+
+    for block in content.split("\n\n") {
+        let mut lines = block.lines().map(str::trim).filter(|line| !line.is_empty()).peekable();
+
+        // The numeric counter line is optional
+        if lines.peek().is_some_and(|line| line.chars().all(|c| c.is_ascii_digit())) {
+            lines.next();
+        }
+
+        let Some(timing) = lines.next() else {
+            continue;
+        };
+        let (start, end) = timing.split_once("-->")
+            .ok_or_else(|| anyhow::anyhow!("Invalid SRT timing line: {}", timing))?;
+        let start_ms = parse_srt_timestamp(start.trim())
+            .ok_or_else(|| anyhow::anyhow!("Invalid SRT timestamp: {}", start.trim()))?;
+        // The end timestamp may be followed by position hints
+        let end = end.split_whitespace().next().unwrap_or("");
+        let end_ms = parse_srt_timestamp(end)
+            .ok_or_else(|| anyhow::anyhow!("Invalid SRT timestamp: {}", end))?;
+
+        let text = lines.map(strip_formatting)
+            .filter(|line| !line.is_empty())
+            .collect::<Vec<_>>()
+            .join(" ");
+        if text.is_empty() {
+            continue;
+        }
+
+        cues.push(SubtitleCue { start_ms, end_ms, text });
+    }
+
+    cues.sort_by_key(|cue| cue.start_ms);
+    Ok(cues)
+}
+
+/// Parse an SRT timestamp (`hh:mm:ss,mmm`) to milliseconds
+fn parse_srt_timestamp(timestamp: &str) -> Option<u32> {
+    let (time, millis) = timestamp.split_once([',', '.'])?;
+    let parts: Vec<&str> = time.split(':').collect();
+    if parts.len() != 3 {
+        return None;
+    }
+
+    let hours: u32 = parts[0].parse().ok()?;
+    let minutes: u32 = parts[1].parse().ok()?;
+    let seconds: u32 = parts[2].parse().ok()?;
+    let millis: u32 = millis.parse().ok()?;
+
+    Some(((hours * 60 + minutes) * 60 + seconds) * 1000 + millis)
+}
+
+/// Remove `<...>` markup and `{...}` override blocks from a subtitle line
+fn strip_formatting(line: &str) -> String {
+    let mut result = String::with_capacity(line.len());
+    let mut closing = None;
+
+    for c in line.chars() {
+        match (closing, c) {
+            (None, '<') => closing = Some('>'),
+            (None, '{') => closing = Some('}'),
+            (None, _) => result.push(c),
+            (Some(end), _) if c == end => closing = None,
+            (Some(_), _) => {}
+        }
+    }
+
+    result.trim().to_string()
+}
+
+/// Select the cues shown within `[start_ms, end_ms)` and shift them to the segment start
+///
+/// A cue that is still on screen when the segment starts is clipped to the segment start.
+/// A cue that ends before the next one starts is followed by an empty line so
+/// players clear the text in the gap.
+pub fn slice_cues(cues: &[SubtitleCue], start_ms: u32, end_ms: u32) -> Vec<LrcLine> {
+    let segment: Vec<&SubtitleCue> = cues.iter()
+        .filter(|cue| cue.end_ms > start_ms && cue.start_ms < end_ms)
+        .collect();
+
+    let mut lines = Vec::new();
+    for (i, cue) in segment.iter().enumerate() {
+        lines.push(LrcLine {
+            time_ms: cue.start_ms.max(start_ms) - start_ms,
+            text: cue.text.clone(),
+        });
+
+        let next_start = segment.get(i + 1).map(|next| next.start_ms.max(start_ms)).unwrap_or(end_ms);
+        if cue.end_ms < next_start && cue.end_ms < end_ms {
+            lines.push(LrcLine {
+                time_ms: cue.end_ms - start_ms,
+                text: String::new(),
+            });
+        }
+    }
+
+    lines
+}
+
+/// Format timed lines as LRC content with `[mm:ss.xx]` timestamps
+pub fn format_lrc(lines: &[LrcLine]) -> String {
+    let mut content = String::new();
+
+    for line in lines {
+        let centis = line.time_ms / 10;
+        content.push_str(&format!(
+            "[{:02}:{:02}.{:02}]{}\n",
+            centis / 6000, (centis / 100) % 60, centis % 100, line.text
+        ));
+    }
+
+    content
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_lrc_timestamp() {
+        assert_eq!(parse_lrc_timestamp("00:00"), Some(0));
+        assert_eq!(parse_lrc_timestamp("01:02"), Some(62_000));
+        assert_eq!(parse_lrc_timestamp("01:02.5"), Some(62_500));
+        assert_eq!(parse_lrc_timestamp("01:02.50"), Some(62_500));
+        assert_eq!(parse_lrc_timestamp("01:02.345"), Some(62_345));
+        assert_eq!(parse_lrc_timestamp("01:02:34"), Some(62_340));
+        assert_eq!(parse_lrc_timestamp("75:00.00"), Some(4_500_000));
+    }
+
+    #[test]
+    fn test_parse_lrc_timestamp_invalid() {
+        assert_eq!(parse_lrc_timestamp("1"), None);
+        assert_eq!(parse_lrc_timestamp("01:2"), None);
+        assert_eq!(parse_lrc_timestamp("01:60.00"), None);
+        assert_eq!(parse_lrc_timestamp("01:02.1234"), None);
+        assert_eq!(parse_lrc_timestamp("aa:02.00"), None);
+    }
+
+    #[test]
+    fn test_parse_lrc() {
+        let content = "[ar:Artist]\n[ti:Title]\n[00:12.00]First line\n[00:17.20][01:05.00]Chorus\n\n[00:30.50]Second line\n";
+
+        let lines = parse_lrc(content).unwrap();
+
+        assert_eq!(lines, vec![
+            LrcLine { time_ms: 12_000, text: "First line".to_string() },
+            LrcLine { time_ms: 17_200, text: "Chorus".to_string() },
+            LrcLine { time_ms: 30_500, text: "Second line".to_string() },
+            LrcLine { time_ms: 65_000, text: "Chorus".to_string() },
+        ]);
+    }
+
+    #[test]
+    fn test_parse_lrc_offset() {
+        let lines = parse_lrc("[offset:+500]\n[00:01.00]Line\n[00:00.20]Early").unwrap();
+
+        assert_eq!(lines[0].time_ms, 0);
+        assert_eq!(lines[1].time_ms, 500);
+    }
+
+    #[test]
+    fn test_parse_lrc_invalid_timestamp() {
+        let result = parse_lrc("[00:01.00]Fine\n[00:99.00]Broken");
+
+        assert!(result.is_err());
+        assert!(result.unwrap_err().to_string().contains("line 2"));
+    }
+
+    #[test]
+    fn test_lyrics_parse_plain_and_synced() {
+        let plain = Lyrics::parse("Just some words\nAnd more words\n").unwrap();
+        assert!(plain.lines.is_none());
+        assert_eq!(plain.plain_text(), "Just some words\nAnd more words");
+        assert_eq!(plain.last_timestamp_ms(), None);
+
+        let synced = Lyrics::parse("[ti:Song]\n[00:05.00]Hello\n[00:10.00]World").unwrap();
+        assert_eq!(synced.plain_text(), "Hello\nWorld");
+        assert_eq!(synced.last_timestamp_ms(), Some(10_000));
+        assert!(!synced.is_empty());
+
+        let empty = Lyrics::parse("[ar:Band]\n[00:00.00]\n[00:05.00] ").unwrap();
+        assert_eq!(empty.lines.as_ref().map(Vec::len), Some(2));
+        assert!(empty.is_empty());
+    }
+
+    #[test]
+    fn test_is_lyrics_path() {
+        assert!(is_lyrics_path("lyrics/track_01.lrc"));
+        assert!(is_lyrics_path("track_01.TXT"));
+        assert!(!is_lyrics_path("Some inline lyrics"));
+        assert!(!is_lyrics_path("Line one\nends with notes.txt"));
+    }
+
+    #[test]
+    fn test_parse_srt() {
+        let content = "1\r\n00:00:01,000 --> 00:00:03,500\r\n<i>First</i> line\r\nwraps here\r\n\r\n2\r\n00:01:02,250 --> 00:01:04,000 X1:0\r\n{\\k20}Karaoke{\\k30} text\r\n";
+
+        let cues = parse_srt(content).unwrap();
+
+        assert_eq!(cues, vec![
+            SubtitleCue { start_ms: 1_000, end_ms: 3_500, text: "First line wraps here".to_string() },
+            SubtitleCue { start_ms: 62_250, end_ms: 64_000, text: "Karaoke text".to_string() },
+        ]);
+    }
+
+    #[test]
+    fn test_parse_srt_invalid_timing() {
+        assert!(parse_srt("1\n00:00:01 -> 00:00:02\nText\n").is_err());
+        assert!(parse_srt("1\n00:00:01,000 --> bogus\nText\n").is_err());
+    }
+
+    #[test]
+    fn test_slice_cues() {
+        let cues = vec![
+            SubtitleCue { start_ms: 1_000, end_ms: 4_000, text: "Before".to_string() },
+            SubtitleCue { start_ms: 10_000, end_ms: 12_000, text: "One".to_string() },
+            SubtitleCue { start_ms: 12_000, end_ms: 14_000, text: "Two".to_string() },
+            SubtitleCue { start_ms: 19_000, end_ms: 25_000, text: "Three".to_string() },
+            SubtitleCue { start_ms: 20_000, end_ms: 22_000, text: "Next track".to_string() },
+        ];
+
+        let lines = slice_cues(&cues, 10_000, 20_000);
+
+        assert_eq!(lines, vec![
+            LrcLine { time_ms: 0, text: "One".to_string() },
+            LrcLine { time_ms: 2_000, text: "Two".to_string() },
+            LrcLine { time_ms: 4_000, text: String::new() },
+            LrcLine { time_ms: 9_000, text: "Three".to_string() },
+        ]);
+    }
+
+    #[test]
+    fn test_slice_cues_overlapping_start() {
+        let cues = vec![
+            SubtitleCue { start_ms: 8_000, end_ms: 11_000, text: "Carried over".to_string() },
+            SubtitleCue { start_ms: 12_000, end_ms: 14_000, text: "One".to_string() },
+        ];
+
+        let lines = slice_cues(&cues, 10_000, 20_000);
+
+        assert_eq!(lines, vec![
+            LrcLine { time_ms: 0, text: "Carried over".to_string() },
+            LrcLine { time_ms: 1_000, text: String::new() },
+            LrcLine { time_ms: 2_000, text: "One".to_string() },
+            LrcLine { time_ms: 4_000, text: String::new() },
+        ]);
+    }
+
+    #[test]
+    fn test_format_lrc_round_trip() {
+        let lines = vec![
+            LrcLine { time_ms: 0, text: "Start".to_string() },
+            LrcLine { time_ms: 62_345, text: "Later".to_string() },
+            LrcLine { time_ms: 3_725_000, text: "Long track".to_string() },
+        ];
+
+        let content = format_lrc(&lines);
+
+        assert_eq!(content, "[00:00.00]Start\n[01:02.34]Later\n[62:05.00]Long track\n");
+        let parsed = parse_lrc(&content).unwrap();
+        assert_eq!(parsed[1].time_ms, 62_340);
+        assert_eq!(parsed[2].text, "Long track");
+    }
+}
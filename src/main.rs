@@ -1,15 +1,19 @@
 use anyhow::Result;
-use clap::Parser;
-use soundpipeline::{config::Config, format_selector, format_parser, pipeline::Pipeline, validator::validate_pipeline, duration_checker::check_durations, file_suggester, settings::Settings, ffmpeg};
+use clap::{Parser, Subcommand};
+use soundpipeline::{cancel, config::Config, ffmpeg::FfmpegRunner, progress::ProgressMode, format_selector, format_parser, pipeline::Pipeline, validator::validate_pipeline, duration_checker::check_durations, file_suggester, settings::Settings, ffmpeg, tag_dump, trash};
 use std::path::PathBuf;
 
 #[derive(Parser, Debug)]
 #[command(
     name = "soundpipeline",
     about = "Extract and convert audio from video files",
-    version
+    version,
+    args_conflicts_with_subcommands = true
 )]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// Path to the YAML configuration file (defaults to soundpipeline.yml)
     #[arg(value_name = "CONFIG")]
     config: Option<PathBuf>,
@@ -22,10 +26,138 @@ struct Args {
     #[arg(long)]
     format: Option<String>,
 
+    /// Write a JSON report of the run to this file
+    #[arg(long, value_name = "FILE")]
+    report: Option<PathBuf>,
+
+    /// How to show progress: terminal bars, or newline-delimited JSON events on stdout
+    #[arg(long, value_enum, value_name = "MODE", default_value_t = ProgressMode::Bars)]
+    progress: ProgressMode,
+
     #[command(flatten)]
     settings: Settings,
 }
 
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Inspect the tags of audio files
+    Tags {
+        #[command(subcommand)]
+        command: TagsCommand,
+    },
+    /// Restore or delete paths that cleanup steps moved to the trash
+    Trash {
+        #[command(subcommand)]
+        command: TrashCommand,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum TagsCommand {
+    /// Print tags, pictures and audio properties of files or glob patterns
+    Dump {
+        /// Files or glob patterns such as "output/*.flac"
+        #[arg(value_name = "FILE", required = true)]
+        files: Vec<String>,
+
+        /// Print JSON instead of a table
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum TrashCommand {
+    /// List the trash batches in the current directory
+    List,
+    /// Move the paths of a batch back to where they were
+    Restore {
+        /// Batch to restore (defaults to the most recent one)
+        #[arg(value_name = "BATCH")]
+        batch: Option<String>,
+    },
+    /// Permanently delete trash batches
+    Purge {
+        /// Batches to delete
+        #[arg(value_name = "BATCH", required_unless_present = "all")]
+        batches: Vec<String>,
+
+        /// Delete every batch
+        #[arg(long, conflicts_with = "batches")]
+        all: bool,
+    },
+}
+
+fn run_tags_command(command: TagsCommand) -> Result<()> {
+    match command {
+        TagsCommand::Dump { files, json } => {
+            let dumps = tag_dump::resolve_paths(&files)?
+                .iter()
+                .map(|path| tag_dump::dump_file(path)
+                    .map_err(|e| anyhow::anyhow!("Failed to read '{}': {}", path.display(), e)))
+                .collect::<Result<Vec<_>>>()?;
+
+            if json {
+                println!("{}", serde_json::to_string_pretty(&dumps)?);
+            } else {
+                print!("{}", tag_dump::format_table(&dumps));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn run_trash_command(command: TrashCommand) -> Result<()> {
+    let working_dir = std::env::current_dir()?;
+    let batches = trash::list_batches(&working_dir)?;
+
+    match command {
+        TrashCommand::List => {
+            if batches.is_empty() {
+                println!("Trash is empty");
+            }
+            for (name, manifest) in &batches {
+                println!("{}  {}  {} paths", name, manifest.created_at, manifest.entries.len());
+                for entry in &manifest.entries {
+                    let suffix = if entry.directory { "/" } else { "" };
+                    println!("  {}{}", entry.original.display(), suffix);
+                }
+            }
+        }
+        TrashCommand::Restore { batch } => {
+            let name = match batch {
+                Some(name) => name,
+                None => match batches.last() {
+                    Some((name, _)) => name.clone(),
+                    None => anyhow::bail!("Trash is empty"),
+                },
+            };
+            let summary = trash::restore(&working_dir, &name)?;
+            println!("Restored {} paths from {}", summary.restored.len(), name);
+            if !summary.skipped.is_empty() {
+                println!("Kept {} paths in the trash because their original location exists:", summary.skipped.len());
+                for path in &summary.skipped {
+                    println!("  {}", path.display());
+                }
+            }
+        }
+        TrashCommand::Purge { batches: names, all } => {
+            let names: Vec<String> = if all {
+                batches.into_iter().map(|(name, _)| name).collect()
+            } else {
+                names
+            };
+            for name in &names {
+                trash::purge(&working_dir, name)?;
+                println!("Deleted {}", name);
+            }
+        }
+    }
+
+    Ok(())
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     let args = Args::parse();
@@ -37,9 +169,19 @@ async fn main() -> Result<()> {
         "soundpipeline=info"
     };
     
-    tracing_subscriber::fmt()
-        .with_env_filter(filter)
-        .init();
+    // Keep stdout free for the JSON events
+    let subscriber = tracing_subscriber::fmt().with_env_filter(filter);
+    if args.progress == ProgressMode::Json {
+        subscriber.with_writer(std::io::stderr).init();
+    } else {
+        subscriber.init();
+    }
+
+    match args.command {
+        Some(Command::Tags { command }) => return run_tags_command(command),
+        Some(Command::Trash { command }) => return run_trash_command(command),
+        None => {}
+    }
 
     // Ensure FFmpeg is available by auto-downloading if needed
     tracing::info!("Checking FFmpeg availability...");
@@ -140,13 +282,11 @@ async fn main() -> Result<()> {
                 ) {
                     Ok(Some(replacement_path)) => {
                         // Update the config with the new file path
-                        if let Some(step) = config.steps.get_mut(check.step_index - 1) {
-                            if let soundpipeline::config::StepConfig::Ffmpeg { input, .. } = step {
-                                let old_input = input.clone();
-                                *input = replacement_path.to_string_lossy().to_string();
-                                tracing::info!("✅ Replaced '{}' with '{}'", old_input, input);
-                                config_modified = true;
-                            }
+                        if let Some(soundpipeline::config::StepConfig::Ffmpeg { input, .. }) = config.steps.get_mut(check.step_index - 1) {
+                            let old_input = input.clone();
+                            *input = replacement_path.to_string_lossy().to_string();
+                            tracing::info!("✅ Replaced '{}' with '{}'", old_input, input);
+                            config_modified = true;
                         }
                     }
                     Ok(None) => {
@@ -217,8 +357,26 @@ async fn main() -> Result<()> {
     tracing::info!("Pipeline validation successful");
 
     // Create and execute pipeline
-    let pipeline = Pipeline::from_config(&config, &selected_format, &working_dir, &encoder_availability)?;
-    pipeline.execute().await?;
+    let ffmpeg_runner = FfmpegRunner::new(settings.ffmpeg_logs.then(|| working_dir.join("logs")));
+    let pipeline = Pipeline::from_config(&config, &selected_format, &working_dir, &encoder_availability, &ffmpeg_runner)?;
+    // Until now Ctrl-C simply exits; from here on running steps clean up after themselves
+    cancel::install_handler()?;
+    let report = pipeline.execute(args.progress.reporter()).await?;
+
+    // Written before bailing so failed runs are reported too
+    if let Some(path) = &args.report {
+        std::fs::write(path, serde_json::to_string_pretty(&report)?)
+            .map_err(|e| anyhow::anyhow!("Failed to write report to {}: {}", path.display(), e))?;
+        tracing::info!("Wrote run report to {}", path.display());
+    }
+
+    if cancel::is_cancelled() && !report.success {
+        eprintln!("Pipeline interrupted; FFmpeg was stopped and incomplete outputs were removed");
+        std::process::exit(cancel::EXIT_CODE);
+    }
+    if let Some(error) = report.error {
+        anyhow::bail!(error);
+    }
 
     tracing::info!("SoundPipeline completed successfully");
     Ok(())
@@ -0,0 +1,236 @@
+use crate::config::{MusicBrainzIds, TagFile};
+use anyhow::Result;
+use serde::Deserialize;
+use std::path::Path;
+
+/// Tag values for one medium of a saved MusicBrainz release
+#[derive(Debug, Clone, Default)]
+pub struct MusicBrainzRelease {
+    /// Tracks of the medium in position order
+    pub tracks: Vec<TagFile>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Release {
+    id: String,
+    title: String,
+    date: Option<String>,
+    #[serde(rename = "artist-credit", default)]
+    artist_credit: Vec<ArtistCredit>,
+    #[serde(rename = "release-group")]
+    release_group: Option<ReleaseGroup>,
+    #[serde(default)]
+    media: Vec<Medium>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ReleaseGroup {
+    id: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Medium {
+    position: u32,
+    #[serde(default)]
+    tracks: Vec<Track>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Track {
+    id: String,
+    position: u32,
+    title: String,
+    #[serde(rename = "artist-credit", default)]
+    artist_credit: Vec<ArtistCredit>,
+    recording: Recording,
+}
+
+#[derive(Debug, Deserialize)]
+struct Recording {
+    id: String,
+    #[serde(default)]
+    isrcs: Vec<String>,
+    #[serde(rename = "artist-credit", default)]
+    artist_credit: Vec<ArtistCredit>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ArtistCredit {
+    name: String,
+    #[serde(default)]
+    joinphrase: String,
+    artist: Artist,
+}
+
+#[derive(Debug, Deserialize)]
+struct Artist {
+    id: String,
+}
+
+impl MusicBrainzRelease {
+    /// Load a release saved from `/ws/2/release/<id>?inc=recordings+artist-credits+isrcs&fmt=json`
+    pub fn load(path: &Path, medium: u32) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("Failed to read MusicBrainz release '{}': {}", path.display(), e))?;
+
+        Self::from_json(&content, medium)
+            .map_err(|e| anyhow::anyhow!("Invalid MusicBrainz release '{}': {}", path.display(), e))
+    }
+
+    pub fn from_json(json: &str, medium: u32) -> Result<Self> {
+        let release: Release = serde_json::from_str(json)?;
+
+        let disk_total = release.media.len() as u32;
+        let Some(selected) = release.media.iter().find(|m| m.position == medium) else {
+            anyhow::bail!("Release has no medium {} (it has {})", medium, disk_total);
+        };
+        if selected.tracks.is_empty() {
+            anyhow::bail!("Medium {} has no tracks; save the release with inc=recordings", medium);
+        }
+
+        let album_artist = credit_name(&release.artist_credit);
+        let release_artist_ids = credit_ids(&release.artist_credit);
+        let date = release.date.filter(|date| !date.is_empty());
+        let year = date.as_deref()
+            .and_then(|date| date.get(..4))
+            .and_then(|year| year.parse().ok());
+
+        let mut tracks: Vec<TagFile> = selected.tracks.iter()
+            .map(|track| {
+                // Track credits can differ from the recording credits on compilations
+                let credit = if track.artist_credit.is_empty() {
+                    &track.recording.artist_credit
+                } else {
+                    &track.artist_credit
+                };
+
+                TagFile {
+                    title: Some(track.title.clone()),
+                    artist: credit_name(credit).or_else(|| album_artist.clone()),
+                    album: Some(release.title.clone()),
+                    album_artist: album_artist.clone(),
+                    track: Some(track.position),
+                    track_total: Some(selected.tracks.len() as u32),
+                    disk: Some(medium),
+                    disk_total: Some(disk_total),
+                    year,
+                    date: date.clone(),
+                    isrc: track.recording.isrcs.first().cloned(),
+                    musicbrainz: Some(MusicBrainzIds {
+                        release_id: Some(release.id.clone()),
+                        release_group_id: release.release_group.as_ref().map(|group| group.id.clone()),
+                        release_artist_ids: release_artist_ids.clone(),
+                        track_id: Some(track.id.clone()),
+                        recording_id: Some(track.recording.id.clone()),
+                        artist_ids: credit_ids(credit).or_else(|| release_artist_ids.clone()),
+                    }),
+                    ..Default::default()
+                }
+            })
+            .collect();
+        tracks.sort_by_key(|track| track.track);
+
+        Ok(Self { tracks })
+    }
+
+    /// Values for the track at the given position on the medium
+    pub fn for_track(&self, track: Option<u32>) -> Option<&TagFile> {
+        let track = track?;
+        self.tracks.iter().find(|t| t.track == Some(track))
+    }
+}
+
+/// Join an artist credit the way MusicBrainz displays it, e.g. `A feat. B`
+fn credit_name(credit: &[ArtistCredit]) -> Option<String> {
+    if credit.is_empty() {
+        return None;
+    }
+
+    Some(credit.iter()
+        .map(|c| format!("{}{}", c.name, c.joinphrase))
+        .collect())
+}
+
+fn credit_ids(credit: &[ArtistCredit]) -> Option<Vec<String>> {
+    if credit.is_empty() {
+        return None;
+    }
+
+    Some(credit.iter().map(|c| c.artist.id.clone()).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RELEASE: &str = r#"{
+        "id": "rel-1",
+        "title": "Live at the Hall",
+        "date": "2024-05-01",
+        "artist-credit": [{"name": "Band", "joinphrase": "", "artist": {"id": "artist-band", "name": "Band"}}],
+        "release-group": {"id": "group-1", "title": "Live at the Hall"},
+        "media": [
+            {"position": 1, "track-count": 2, "tracks": [
+                {"id": "track-2", "position": 2, "number": "2", "title": "Song",
+                 "artist-credit": [
+                    {"name": "Band", "joinphrase": " feat. ", "artist": {"id": "artist-band"}},
+                    {"name": "Guest", "joinphrase": "", "artist": {"id": "artist-guest"}}
+                 ],
+                 "recording": {"id": "rec-2", "title": "Song", "isrcs": ["GBAAA2400002"]}},
+                {"id": "track-1", "position": 1, "number": "1", "title": "Intro",
+                 "recording": {"id": "rec-1", "title": "Intro"}}
+            ]},
+            {"position": 2, "tracks": [
+                {"id": "track-3", "position": 1, "title": "Encore", "recording": {"id": "rec-3"}}
+            ]}
+        ]
+    }"#;
+
+    #[test]
+    fn test_from_json() {
+        let release = MusicBrainzRelease::from_json(RELEASE, 1).unwrap();
+
+        assert_eq!(release.tracks.len(), 2);
+
+        let intro = release.for_track(Some(1)).unwrap();
+        assert_eq!(intro.title, Some("Intro".to_string()));
+        assert_eq!(intro.artist, Some("Band".to_string()));
+        assert_eq!(intro.album, Some("Live at the Hall".to_string()));
+        assert_eq!(intro.album_artist, Some("Band".to_string()));
+        assert_eq!(intro.track_total, Some(2));
+        assert_eq!((intro.disk, intro.disk_total), (Some(1), Some(2)));
+        assert_eq!(intro.year, Some(2024));
+        assert_eq!(intro.date, Some("2024-05-01".to_string()));
+        assert_eq!(intro.isrc, None);
+
+        let song = release.for_track(Some(2)).unwrap();
+        assert_eq!(song.artist, Some("Band feat. Guest".to_string()));
+        assert_eq!(song.isrc, Some("GBAAA2400002".to_string()));
+        let ids = song.musicbrainz.as_ref().unwrap();
+        assert_eq!(ids.release_id, Some("rel-1".to_string()));
+        assert_eq!(ids.release_group_id, Some("group-1".to_string()));
+        assert_eq!(ids.track_id, Some("track-2".to_string()));
+        assert_eq!(ids.recording_id, Some("rec-2".to_string()));
+        assert_eq!(ids.artist_ids, Some(vec!["artist-band".to_string(), "artist-guest".to_string()]));
+        assert_eq!(ids.release_artist_ids, Some(vec!["artist-band".to_string()]));
+
+        assert!(release.for_track(Some(3)).is_none());
+        assert!(release.for_track(None).is_none());
+    }
+
+    #[test]
+    fn test_from_json_medium() {
+        let release = MusicBrainzRelease::from_json(RELEASE, 2).unwrap();
+        assert_eq!(release.tracks[0].title, Some("Encore".to_string()));
+        assert_eq!(release.tracks[0].disk, Some(2));
+
+        let error = MusicBrainzRelease::from_json(RELEASE, 3).unwrap_err();
+        assert!(error.to_string().contains("no medium 3"));
+    }
+
+    #[test]
+    fn test_from_json_invalid() {
+        assert!(MusicBrainzRelease::from_json("{}", 1).is_err());
+        assert!(MusicBrainzRelease::from_json(r#"{"id": "x", "title": "y", "media": [{"position": 1}]}"#, 1).is_err());
+    }
+}
@@ -1,6 +1,7 @@
 pub mod step;
 pub mod ffmpeg_step;
 pub mod split_step;
+pub mod subtitle_step;
 pub mod transcode_step;
 pub mod tag_step;
 pub mod cleanup_step;
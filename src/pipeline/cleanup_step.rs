@@ -1,111 +1,218 @@
 use super::Step;
+use crate::config::{CleanupMode, RunCondition};
+use crate::run_report::{CleanupSummary, StepReport};
+use crate::trash::{TrashBatch, TRASH_DIR};
+use crate::workspace::resolve_within;
 use anyhow::Result;
 use async_trait::async_trait;
-use std::path::Path;
-use tracing::{info, warn};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tracing::{debug, info, warn};
 
 pub struct CleanupStep {
     files: Vec<String>,
+    /// Outputs recorded by earlier steps; when set, only those are removed
+    created: Option<Arc<Mutex<HashSet<PathBuf>>>>,
+    mode: CleanupMode,
+    when: RunCondition,
+    /// Fail the step when any path could not be removed
+    strict: bool,
+}
+
+struct CleanupRun<'a> {
+    working_dir: &'a Path,
+    /// The outputs of earlier steps when only those are removed
+    created: Option<HashSet<PathBuf>>,
+    summary: CleanupSummary,
+    /// Created with the first path moved to the trash
+    trash: Option<TrashBatch>,
+}
+
+impl CleanupRun<'_> {
+    fn fail(&mut self, message: String) {
+        warn!("{}", message);
+        self.summary.failed += 1;
+        self.summary.failures.push(message);
+    }
 }
 
 impl CleanupStep {
-    pub fn new(files: Vec<String>) -> Self {
+    pub fn new(files: Vec<String>, created: Option<Arc<Mutex<HashSet<PathBuf>>>>, mode: CleanupMode, when: RunCondition, strict: bool) -> Self {
         Self {
             files,
+            created,
+            mode,
+            when,
+            strict,
+        }
+    }
+
+    /// Paths a cleanup entry refers to: the path itself if it exists, otherwise its glob matches
+    fn matches(working_dir: &Path, file_pattern: &str, run: &mut CleanupRun) -> Vec<PathBuf> {
+        let path = working_dir.join(file_pattern);
+        if path.symlink_metadata().is_ok() {
+            return vec![path];
+        }
+
+        // Unlike step inputs, cleanup patterns match hidden files, so `split/*` also
+        // removes the temporary outputs a crash left behind
+        let pattern_str = path.to_string_lossy();
+        let paths = match glob::glob(&pattern_str) {
+            Ok(paths) => paths,
+            Err(e) => {
+                run.fail(format!("Invalid glob pattern {}: {}", file_pattern, e));
+                return Vec::new();
+            }
+        };
+
+        let mut matched = Vec::new();
+        for entry in paths {
+            match entry {
+                Ok(path) => matched.push(path),
+                Err(e) => run.fail(format!("Glob pattern error for {}: {}", pattern_str, e)),
+            }
+        }
+        if matched.is_empty() {
+            warn!("No files matched pattern: {}", file_pattern);
+        }
+        matched
+    }
+
+    fn remove_path(&self, path: &Path, run: &mut CleanupRun) {
+        let Ok(metadata) = path.symlink_metadata() else {
+            // Already removed through an earlier entry
+            return;
+        };
+
+        if let Some(created) = &run.created {
+            let (written, other) = if metadata.is_dir() {
+                match count_written(path, created) {
+                    Ok(counts) => counts,
+                    Err(e) => {
+                        run.fail(format!("Failed to read directory {}: {}", path.display(), e));
+                        return;
+                    }
+                }
+            } else {
+                (usize::from(created.contains(path)), usize::from(!created.contains(path)))
+            };
+            if written == 0 {
+                info!("Keeping {}: it was not written by this run", path.display());
+                run.summary.kept += 1;
+                return;
+            }
+            if other > 0 {
+                self.remove_written_in(path, run);
+                return;
+            }
+        }
+
+        let kind = if metadata.is_dir() { "directory" } else { "file" };
+        let result = match self.mode {
+            CleanupMode::Delete => {
+                // Symlinks are removed themselves, never what they point to
+                let result = if metadata.is_dir() {
+                    std::fs::remove_dir_all(path)
+                } else {
+                    std::fs::remove_file(path)
+                };
+                result.map_err(anyhow::Error::from)
+            }
+            CleanupMode::Trash => Self::move_to_trash(path, run),
+        };
+        match result {
+            Ok(_) => {
+                match self.mode {
+                    CleanupMode::Delete => info!("Removed {}: {}", kind, path.display()),
+                    CleanupMode::Trash => info!("Moved {} to trash: {}", kind, path.display()),
+                }
+                run.summary.removed += 1;
+            }
+            Err(e) => run.fail(format!("Failed to remove {} {}: {}", kind, path.display(), e)),
+        }
+    }
+
+    fn move_to_trash(path: &Path, run: &mut CleanupRun) -> Result<()> {
+        let batch = match &mut run.trash {
+            Some(batch) => batch,
+            None => run.trash.insert(TrashBatch::create(run.working_dir)?),
+        };
+        batch.move_in(path)
+    }
+
+    /// Remove what the run wrote inside a directory that also holds other files
+    fn remove_written_in(&self, dir: &Path, run: &mut CleanupRun) {
+        debug!("Removing only the outputs of this run from {}", dir.display());
+        let entries = match std::fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) => {
+                run.fail(format!("Failed to read directory {}: {}", dir.display(), e));
+                return;
+            }
+        };
+
+        for entry in entries.flatten() {
+            self.remove_path(&entry.path(), run);
+        }
+    }
+}
+
+/// How many files below `dir` the run wrote, and how many other files and symlinks there are
+fn count_written(dir: &Path, created: &HashSet<PathBuf>) -> std::io::Result<(usize, usize)> {
+    let (mut written, mut other) = (0, 0);
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            let (w, o) = count_written(&entry.path(), created)?;
+            written += w;
+            other += o;
+        } else if created.contains(&entry.path()) {
+            written += 1;
+        } else {
+            other += 1;
         }
     }
+    Ok((written, other))
 }
 
 #[async_trait]
 impl Step for CleanupStep {
-    async fn execute(&self, working_dir: &Path) -> Result<()> {
+    async fn execute(&self, working_dir: &Path, report: &mut StepReport) -> Result<()> {
         info!("Executing Cleanup step: {} files/directories to remove", self.files.len());
         
-        let mut removed_count = 0;
-        let mut failed_count = 0;
+        let mut run = CleanupRun {
+            working_dir,
+            created: self.created.as_ref().map(|created| created.lock().unwrap().clone()),
+            summary: CleanupSummary::default(),
+            trash: None,
+        };
+        let trash_dir = working_dir.canonicalize()?.join(TRASH_DIR);
         
         for file_pattern in &self.files {
-            let path = working_dir.join(file_pattern);
-            
-            if path.exists() {
-                if path.is_dir() {
-                    match std::fs::remove_dir_all(&path) {
-                        Ok(_) => {
-                            info!("Removed directory: {}", path.display());
-                            removed_count += 1;
-                        }
-                        Err(e) => {
-                            warn!("Failed to remove directory {}: {}", path.display(), e);
-                            failed_count += 1;
-                        }
-                    }
-                } else {
-                    match std::fs::remove_file(&path) {
-                        Ok(_) => {
-                            info!("Removed file: {}", path.display());
-                            removed_count += 1;
-                        }
-                        Err(e) => {
-                            warn!("Failed to remove file {}: {}", path.display(), e);
-                            failed_count += 1;
-                        }
-                    }
-                }
-            } else {
-                // Check if it's a glob pattern
-                let pattern_str = path.to_string_lossy();
-                match glob::glob(&pattern_str) {
-                    Ok(paths) => {
-                        let mut pattern_matched = false;
-                        for entry in paths {
-                            match entry {
-                                Ok(path) => {
-                                    pattern_matched = true;
-                                    if path.is_dir() {
-                                        match std::fs::remove_dir_all(&path) {
-                                            Ok(_) => {
-                                                info!("Removed directory: {}", path.display());
-                                                removed_count += 1;
-                                            }
-                                            Err(e) => {
-                                                warn!("Failed to remove directory {}: {}", path.display(), e);
-                                                failed_count += 1;
-                                            }
-                                        }
-                                    } else {
-                                        match std::fs::remove_file(&path) {
-                                            Ok(_) => {
-                                                info!("Removed file: {}", path.display());
-                                                removed_count += 1;
-                                            }
-                                            Err(e) => {
-                                                warn!("Failed to remove file {}: {}", path.display(), e);
-                                                failed_count += 1;
-                                            }
-                                        }
-                                    }
-                                }
-                                Err(e) => {
-                                    warn!("Glob pattern error for {}: {}", pattern_str, e);
-                                    failed_count += 1;
-                                }
-                            }
-                        }
-                        if !pattern_matched {
-                            warn!("No files matched pattern: {}", file_pattern);
-                        }
-                    }
-                    Err(e) => {
-                        warn!("Invalid glob pattern {}: {}", file_pattern, e);
-                        failed_count += 1;
+            for path in Self::matches(working_dir, file_pattern, &mut run) {
+                match resolve_within(working_dir, &path) {
+                    // Patterns like `*` also match the trash, which cleanup never touches
+                    Ok(path) if path.starts_with(&trash_dir) => {
+                        debug!("Skipping trash directory: {}", path.display());
                     }
+                    Ok(path) => self.remove_path(&path, &mut run),
+                    Err(e) => run.fail(format!("Refusing to remove: {}", e)),
                 }
             }
         }
         
-        info!("Cleanup step completed: {} removed, {} failed", removed_count, failed_count);
+        let summary = run.summary;
+        info!("Cleanup step completed: {} removed, {} kept, {} failed", summary.removed, summary.kept, summary.failed);
+        if let Some(batch) = &run.trash {
+            info!("Moved files can be restored from {}", batch.dir().display());
+        }
+        report.cleanup = Some(summary.clone());
         
-        if failed_count > 0 && removed_count == 0 {
+        if self.strict && summary.failed > 0 {
+            anyhow::bail!("{} paths could not be removed:\n  {}", summary.failed, summary.failures.join("\n  "));
+        }
+        if summary.failed > 0 && summary.removed == 0 {
             anyhow::bail!("All cleanup operations failed");
         }
         
@@ -115,4 +222,62 @@ impl Step for CleanupStep {
     fn name(&self) -> &str {
         "Cleanup"
     }
-}
\ No newline at end of file
+
+    fn run_condition(&self) -> RunCondition {
+        self.when
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::workspace::PendingOutput;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_only_created_removes_recorded_outputs() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        std::fs::create_dir_all(root.join("split")).unwrap();
+        std::fs::create_dir_all(root.join("new/nested")).unwrap();
+        for file in ["split/notes.txt", "split/01.wav", "new/nested/02.wav", "old.wav"] {
+            std::fs::write(root.join(file), "").unwrap();
+        }
+        // split/01.wav existed before and was overwritten by a step
+        let created = HashSet::from([root.join("split/01.wav"), root.join("new/nested/02.wav")]);
+
+        let step = CleanupStep::new(
+            vec!["split".to_string(), "new".to_string(), "*.wav".to_string()],
+            Some(Arc::new(Mutex::new(created))),
+            CleanupMode::Delete,
+            RunCondition::Success,
+            false,
+        );
+        let mut report = StepReport::new("Cleanup");
+        step.execute(dir.path(), &mut report).await.unwrap();
+
+        assert!(!root.join("split/01.wav").exists());
+        assert!(root.join("split/notes.txt").exists());
+        assert!(!root.join("new").exists());
+        assert!(root.join("old.wav").exists());
+        let summary = report.cleanup.unwrap();
+        assert_eq!((summary.removed, summary.kept, summary.failed), (2, 2, 0));
+    }
+
+    #[tokio::test]
+    async fn test_wildcards_remove_stale_temporary_outputs() {
+        let dir = TempDir::new().unwrap();
+        std::fs::create_dir(dir.path().join("split")).unwrap();
+        std::fs::write(dir.path().join("split/01.wav"), "").unwrap();
+        let stale = PendingOutput::new(&dir.path().join("split/02.wav")).temp_path().to_path_buf();
+        std::fs::write(&stale, "partial").unwrap();
+
+        let step = CleanupStep::new(vec!["split/*".to_string()], None, CleanupMode::Delete, RunCondition::Success, false);
+        let mut report = StepReport::new("Cleanup");
+        step.execute(dir.path(), &mut report).await.unwrap();
+
+        assert!(!dir.path().join("split/01.wav").exists());
+        assert!(!stale.exists());
+        assert_eq!(report.cleanup.unwrap().removed, 2);
+    }
+}
@@ -1,27 +1,40 @@
-use crate::config::{Config, StepConfig, SelectedFormat};
-use crate::ffmpeg::EncoderAvailability;
-use crate::pipeline::{Step, ffmpeg_step::FfmpegStep, split_step::SplitStep, transcode_step::TranscodeStep, tag_step::TagStep, cleanup_step::CleanupStep};
+use crate::cancel;
+use crate::config::{Config, RunCondition, StepConfig, SelectedFormat, TagStepConfig};
+use crate::ffmpeg::{EncoderAvailability, FfmpegRunner};
+use crate::filename_pattern::FilenamePattern;
+use crate::musicbrainz::MusicBrainzRelease;
+use crate::tag_import::load_tag_list;
+use crate::pipeline::{Step, ffmpeg_step::FfmpegStep, split_step::SplitStep, subtitle_step::SubtitleStep, transcode_step::TranscodeStep, tag_step::{TagStep, TagOptions}, cleanup_step::CleanupStep};
+use crate::progress::{ProgressEvent, ProgressReporter};
+use crate::run_report::{RunReport, StepReport, StepStatus};
 use anyhow::Result;
-use std::path::Path;
-use tracing::{info, debug};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tracing::{info, debug, error};
 
 pub struct Pipeline {
     steps: Vec<Box<dyn Step>>,
     working_dir: std::path::PathBuf,
+    /// Outputs recorded by the steps so far, for cleanup steps limited to what the run creates
+    created: Arc<Mutex<HashSet<PathBuf>>>,
 }
 
 impl Pipeline {
-    pub fn from_config(config: &Config, selected_format: &SelectedFormat, working_dir: impl AsRef<Path>, encoder_availability: &EncoderAvailability) -> Result<Self> {
+    pub fn from_config(config: &Config, selected_format: &SelectedFormat, working_dir: impl AsRef<Path>, encoder_availability: &EncoderAvailability, ffmpeg: &FfmpegRunner) -> Result<Self> {
         let working_dir = working_dir.as_ref().to_path_buf();
         let mut steps: Vec<Box<dyn Step>> = Vec::new();
+        let created = Arc::new(Mutex::new(HashSet::new()));
         
-        for step_config in &config.steps {
+        for (idx, step_config) in config.steps.iter().enumerate() {
             match step_config {
                 StepConfig::Ffmpeg { input, output, args, input_duration: _ } => {
                     let step = FfmpegStep::new(
                         input.clone(),
                         output.clone(),
                         args.clone(),
+                        ffmpeg.clone(),
                     );
                     steps.push(Box::new(step));
                 }
@@ -33,6 +46,18 @@ impl Pipeline {
                     );
                     steps.push(Box::new(step));
                 }
+                StepConfig::Subtitles { input, output_dir, stream, files: _ } => {
+                    let files = config.subtitle_segments(idx)
+                        .ok_or_else(|| anyhow::anyhow!("Subtitles step has no files and no preceding split step"))?;
+                    let step = SubtitleStep::new(
+                        input.clone(),
+                        output_dir.clone(),
+                        *stream,
+                        files,
+                        ffmpeg.clone(),
+                    );
+                    steps.push(Box::new(step));
+                }
                 StepConfig::Transcode { input_dir, output_dir, files } => {
                     let step = TranscodeStep::new(
                         input_dir.clone(),
@@ -42,18 +67,47 @@ impl Pipeline {
                         selected_format.bitrate.clone(),
                         selected_format.bit_depth,
                         encoder_availability.clone(),
+                        ffmpeg.clone(),
                     );
                     steps.push(Box::new(step));
                 }
-                StepConfig::Tag { input_dir, files } => {
+                StepConfig::Tag(tag) => {
+                    let TagStepConfig { input_dir, files, tags_from, defaults: _, artwork, album_art_from_video, from_filename, from_source, musicbrainz, verify, clear_existing, remove, remove_tag_types, id3 } = tag.as_ref();
+                    // Entries from the track list come first so `files` can override them
+                    let mut entries = match tags_from {
+                        Some(path) => load_tag_list(&working_dir.join(path))?,
+                        None => Vec::new(),
+                    };
+                    entries.extend(files.iter().cloned());
+                    
                     let step = TagStep::new(
                         input_dir.clone(),
-                        files.clone(),
+                        entries,
+                        TagOptions {
+                            defaults: tag.merged_defaults(config.album.as_ref()),
+                            artwork: artwork.clone(),
+                            album_art_from_video: album_art_from_video.clone(),
+                            from_filename: from_filename.as_deref().map(FilenamePattern::parse).transpose()?,
+                            from_source: from_source.clone(),
+                            musicbrainz: musicbrainz.as_ref()
+                                .map(|source| MusicBrainzRelease::load(&working_dir.join(&source.release), source.medium))
+                                .transpose()?,
+                            verify: *verify,
+                            clear_existing: clear_existing.unwrap_or(false),
+                            remove: remove.clone().unwrap_or_default(),
+                            remove_tag_types: remove_tag_types.clone().unwrap_or_default(),
+                            id3: id3.clone().unwrap_or_default(),
+                        },
+                        ffmpeg.clone(),
                     );
                     steps.push(Box::new(step));
                 }
-                StepConfig::Cleanup { files } => {
-                    let step = CleanupStep::new(files.clone());
+                StepConfig::Cleanup { files, only_created, mode, when, strict } => {
+                    let created = match only_created {
+                        Some(true) => Some(created.clone()),
+                        _ => None,
+                    };
+                    let step = CleanupStep::new(files.clone(), created, mode.unwrap_or_default(), when.unwrap_or_default(), strict.unwrap_or(false));
                     steps.push(Box::new(step));
                 }
             }
@@ -62,10 +116,21 @@ impl Pipeline {
         Ok(Pipeline {
             steps,
             working_dir,
+            created,
         })
     }
     
-    pub async fn execute(&self) -> Result<()> {
+    /// Remember what a step wrote, as the canonical paths cleanup steps resolve matches to
+    fn record_outputs(&self, step_report: &StepReport) {
+        let mut created = self.created.lock().unwrap();
+        for file in &step_report.files_created {
+            created.insert(file.path.canonicalize().unwrap_or_else(|_| file.path.clone()));
+        }
+    }
+    
+    /// Run the steps in order. A failing step is recorded in the report rather than returned
+    /// as an error, so the report is available either way.
+    pub async fn execute(&self, progress: Arc<dyn ProgressReporter>) -> Result<RunReport> {
         info!("Starting pipeline execution with {} steps", self.steps.len());
         debug!("Working directory: {}", self.working_dir.display());
         
@@ -75,20 +140,86 @@ impl Pipeline {
             info!("Created working directory: {}", self.working_dir.display());
         }
         
+        // The first error; later steps only run if they are meant to run after a failure
+        let mut failure: Option<anyhow::Error> = None;
+        let mut report = RunReport::default();
+        let started = Instant::now();
+        
         for (i, step) in self.steps.iter().enumerate() {
+            let mut step_report = StepReport::new(step.name()).with_progress(progress.clone());
+            let condition = step.run_condition();
+            // Nothing runs after an interruption, not even cleanup
+            let interrupted = cancel::is_cancelled();
+            let runs = !interrupted && match failure {
+                None => condition != RunCondition::Failure,
+                Some(_) => condition != RunCondition::Success,
+            };
+            if !runs {
+                if interrupted {
+                    info!("Skipping step {}/{}: {} (interrupted)", i + 1, self.steps.len(), step.name());
+                } else {
+                    info!("Skipping step {}/{}: {} (when: {:?})", i + 1, self.steps.len(), step.name(), condition);
+                }
+                step_report.status = StepStatus::Skipped;
+                progress.report(&ProgressEvent::StepFinished {
+                    step: step.name(),
+                    index: i + 1,
+                    status: StepStatus::Skipped,
+                    duration_seconds: 0.0,
+                });
+                report.steps.push(step_report);
+                continue;
+            }
+            
             info!("Executing step {}/{}: {}", i + 1, self.steps.len(), step.name());
+            progress.report(&ProgressEvent::StepStarted { step: step.name(), index: i + 1, total: self.steps.len() });
             
-            match step.execute(&self.working_dir).await {
+            let step_started = Instant::now();
+            let result = step.execute(&self.working_dir, &mut step_report).await;
+            step_report.duration_seconds = step_started.elapsed().as_secs_f64();
+            self.record_outputs(&step_report);
+            step_report.describe_outputs(&self.working_dir);
+            match result {
                 Ok(()) => {
                     info!("Step {}/{} completed successfully", i + 1, self.steps.len());
                 }
                 Err(e) => {
-                    anyhow::bail!("Step {}/{} failed: {}", i + 1, self.steps.len(), e);
+                    step_report.status = StepStatus::Failed;
+                    step_report.error = Some(format!("{:#}", e));
+                    let error = anyhow::anyhow!("Step {}/{} failed: {}", i + 1, self.steps.len(), e);
+                    match failure {
+                        // Keep the original error; a failing cleanup after it is only logged
+                        Some(_) => error!("{}", error),
+                        None => failure = Some(error),
+                    }
                 }
             }
+            progress.report(&ProgressEvent::StepFinished {
+                step: step.name(),
+                index: i + 1,
+                status: step_report.status,
+                duration_seconds: step_report.duration_seconds,
+            });
+            report.steps.push(step_report);
         }
         
-        info!("Pipeline execution completed successfully");
-        Ok(())
+        report.duration_seconds = started.elapsed().as_secs_f64();
+        report.success = failure.is_none();
+        report.error = failure.map(|e| e.to_string());
+        progress.report(&ProgressEvent::RunFinished {
+            success: report.success,
+            duration_seconds: report.duration_seconds,
+            error: report.error.as_deref(),
+        });
+        
+        info!("Run summary:");
+        for line in report.summary().lines() {
+            info!("  {}", line);
+        }
+        
+        if report.success {
+            info!("Pipeline execution completed successfully");
+        }
+        Ok(report)
     }
-}
\ No newline at end of file
+}
@@ -1,10 +1,42 @@
 use crate::config::SplitFile;
+use crate::cancel;
 use crate::pipeline::Step;
+use crate::progress::FileProgress;
+use crate::run_report::StepReport;
+use crate::workspace::PendingOutput;
 use anyhow::Result;
 use async_trait::async_trait;
 use hound::{WavReader, WavWriter};
 use std::path::Path;
-use tracing::{info, debug, warn};
+use std::time::{Duration, Instant};
+use tracing::{info, debug};
+
+/// Parse a `MM:SS.sss` or `H:MM:SS.sss` timestamp to seconds
+pub fn parse_timestamp(timestamp: &str) -> Result<f64> {
+    let parts: Vec<&str> = timestamp.split(':').collect();
+    
+    match parts.len() {
+        2 => {
+            // MM:SS.sss or MM:SS.ssssss format
+            let minutes: u32 = parts[0].parse()?;
+            let seconds: f64 = parts[1].parse()?;
+            Ok(minutes as f64 * 60.0 + seconds)
+        }
+        3 => {
+            // H:MM:SS.sss or H:MM:SS.ssssss format
+            let hours: u32 = parts[0].parse()?;
+            let minutes: u32 = parts[1].parse()?;
+            let seconds: f64 = parts[2].parse()?;
+            Ok(hours as f64 * 3600.0 + minutes as f64 * 60.0 + seconds)
+        }
+        _ => {
+            anyhow::bail!("Invalid timestamp format: {}. Expected h:mm:ss.SSS or h:mm:ss.SSSSSS", timestamp);
+        }
+    }
+}
+
+/// How often the copy loop reports progress
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
 
 pub struct SplitStep {
     pub input: String,
@@ -21,29 +53,6 @@ impl SplitStep {
         }
     }
 
-    fn parse_timestamp(&self, timestamp: &str) -> Result<f64> {
-        let parts: Vec<&str> = timestamp.split(':').collect();
-        
-        match parts.len() {
-            2 => {
-                // MM:SS.sss or MM:SS.ssssss format
-                let minutes: u32 = parts[0].parse()?;
-                let seconds: f64 = parts[1].parse()?;
-                Ok(minutes as f64 * 60.0 + seconds)
-            }
-            3 => {
-                // H:MM:SS.sss or H:MM:SS.ssssss format
-                let hours: u32 = parts[0].parse()?;
-                let minutes: u32 = parts[1].parse()?;
-                let seconds: f64 = parts[2].parse()?;
-                Ok(hours as f64 * 3600.0 + minutes as f64 * 60.0 + seconds)
-            }
-            _ => {
-                anyhow::bail!("Invalid timestamp format: {}. Expected h:mm:ss.SSS or h:mm:ss.SSSSSS", timestamp);
-            }
-        }
-    }
-
     fn seconds_to_sample_index(&self, seconds: f64, sample_rate: u32) -> usize {
         (seconds * sample_rate as f64).round() as usize
     }
@@ -51,7 +60,7 @@ impl SplitStep {
 
 #[async_trait]
 impl Step for SplitStep {
-    async fn execute(&self, working_dir: &Path) -> Result<()> {
+    async fn execute(&self, working_dir: &Path, report: &mut StepReport) -> Result<()> {
         info!("Executing Split step: {} -> {}", self.input, self.output_dir);
         
         let input_path = working_dir.join(&self.input);
@@ -74,8 +83,8 @@ impl Step for SplitStep {
         // Sort files by start time and validate no overlaps
         let mut sorted_files = self.files.clone();
         for file in &mut sorted_files {
-            file.start_seconds = self.parse_timestamp(&file.start)?;
-            file.end_seconds = self.parse_timestamp(&file.end)?;
+            file.start_seconds = parse_timestamp(&file.start)?;
+            file.end_seconds = parse_timestamp(&file.end)?;
         }
         sorted_files.sort_by(|a, b| a.start_seconds.partial_cmp(&b.start_seconds).unwrap());
         
@@ -134,12 +143,16 @@ impl Step for SplitStep {
             // Create output file
             let output_file_path = output_dir_path.join(&split_file.file);
             debug!("Writing to: {}", output_file_path.display());
+            report.start_file(&output_file_path, i + 1, sorted_files.len());
             
-            let mut writer = WavWriter::create(&output_file_path, spec)?;
+            // Written under a temporary name until the segment is complete
+            let pending = PendingOutput::new(&output_file_path);
+            let mut writer = WavWriter::create(pending.temp_path(), spec)?;
             
             // Read and write samples for this segment
             let mut samples_written = 0;
             let target_samples = end_frame - start_frame;
+            let mut last_progress = Instant::now();
             
             while samples_written < target_samples {
                 match samples_iter.next() {
@@ -147,17 +160,30 @@ impl Step for SplitStep {
                         writer.write_sample(sample)?;
                         samples_written += 1;
                         current_sample_index += 1;
+                        // Checking the clock for every sample would slow the copy down
+                        if samples_written % 65536 == 0 && last_progress.elapsed() >= PROGRESS_INTERVAL {
+                            cancel::check()?;
+                            let channels = spec.channels as u64;
+                            let progress = FileProgress::from_samples(
+                                samples_written as u64 / channels,
+                                target_samples as u64 / channels,
+                                spec.sample_rate,
+                            );
+                            report.file_progress(&output_file_path, &progress);
+                            last_progress = Instant::now();
+                        }
                     }
                     Some(Err(e)) => return Err(e.into()),
                     None => {
-                        warn!("End of file reached after {} samples, expected {}", 
-                              samples_written, target_samples);
+                        report.warn(format!("{}: end of file reached after {} samples, expected {}",
+                                            split_file.file, samples_written, target_samples));
                         break;
                     }
                 }
             }
             
             writer.finalize()?;
+            pending.commit()?;
             
             let duration_samples = samples_written / spec.channels as usize;
             let duration_seconds = duration_samples as f64 / spec.sample_rate as f64;
@@ -165,6 +191,7 @@ impl Step for SplitStep {
             
             info!("Created: {} ({:.3}s, {} bytes)", 
                   output_file_path.display(), duration_seconds, file_size);
+            report.add_output(&output_file_path);
         }
         
         info!("Split step completed successfully");
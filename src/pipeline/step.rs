@@ -1,9 +1,17 @@
+use crate::config::RunCondition;
+use crate::run_report::StepReport;
 use anyhow::Result;
 use async_trait::async_trait;
 use std::path::Path;
 
 #[async_trait]
 pub trait Step: Send + Sync {
-    async fn execute(&self, working_dir: &Path) -> Result<()>;
+    /// Run the step, recording anything worth reporting in `report`
+    async fn execute(&self, working_dir: &Path, report: &mut StepReport) -> Result<()>;
     fn name(&self) -> &str;
+
+    /// Whether the step runs after an earlier step failed
+    fn run_condition(&self) -> RunCondition {
+        RunCondition::Success
+    }
 }
\ No newline at end of file
@@ -0,0 +1,101 @@
+use crate::config::SplitFile;
+use crate::ffmpeg::FfmpegRunner;
+use crate::lyrics::{format_lrc, parse_srt, slice_cues};
+use crate::pipeline::Step;
+use crate::pipeline::split_step::parse_timestamp;
+use crate::run_report::StepReport;
+use crate::workspace::PendingOutput;
+use anyhow::Result;
+use async_trait::async_trait;
+use ffmpeg_sidecar::command::FfmpegCommand;
+use std::path::Path;
+use tracing::{info, debug};
+
+pub struct SubtitleStep {
+    pub input: String,
+    pub output_dir: String,
+    pub stream: u32,
+    pub files: Vec<SplitFile>,
+    pub ffmpeg: FfmpegRunner,
+}
+
+impl SubtitleStep {
+    pub fn new(input: String, output_dir: String, stream: u32, files: Vec<SplitFile>, ffmpeg: FfmpegRunner) -> Self {
+        Self {
+            input,
+            output_dir,
+            stream,
+            files,
+            ffmpeg,
+        }
+    }
+
+    /// Extract the selected subtitle stream to an SRT file with FFmpeg
+    fn extract_srt(&self, input_path: &Path, srt_path: &Path, report: &mut StepReport) -> Result<()> {
+        let mut command = FfmpegCommand::new();
+        command
+            .input(input_path)
+            .overwrite()
+            .map(format!("0:s:{}", self.stream))
+            .format("srt")
+            .output(srt_path);
+
+        report.add_command(&command);
+        self.ffmpeg.run(&mut command, &format!("extract subtitle stream {} from {}", self.stream, input_path.display()), srt_path, |_| {})
+    }
+}
+
+#[async_trait]
+impl Step for SubtitleStep {
+    async fn execute(&self, working_dir: &Path, report: &mut StepReport) -> Result<()> {
+        info!("Executing Subtitles step: {} (stream {}) -> {}", self.input, self.stream, self.output_dir);
+
+        let input_path = working_dir.join(&self.input);
+        let output_dir_path = working_dir.join(&self.output_dir);
+
+        // Check if input file exists
+        if !input_path.exists() {
+            anyhow::bail!("Input file does not exist: {}", input_path.display());
+        }
+
+        // Create output directory if it doesn't exist
+        if !output_dir_path.exists() {
+            std::fs::create_dir_all(&output_dir_path)?;
+            debug!("Created output directory: {}", output_dir_path.display());
+        }
+
+        let srt_path = output_dir_path.join(".subtitles.srt");
+        let extracted = self.extract_srt(&input_path, &srt_path, report)
+            .and_then(|_| Ok(std::fs::read_to_string(&srt_path)?));
+        if srt_path.exists() {
+            std::fs::remove_file(&srt_path)?;
+        }
+        let cues = parse_srt(&extracted?)?;
+
+        info!("Extracted {} subtitle cues", cues.len());
+
+        for (i, segment) in self.files.iter().enumerate() {
+            info!("Processing segment {}/{}: {}", i + 1, self.files.len(), segment.file);
+
+            let start_ms = (parse_timestamp(&segment.start)? * 1000.0).round() as u32;
+            let end_ms = (parse_timestamp(&segment.end)? * 1000.0).round() as u32;
+
+            let lines = slice_cues(&cues, start_ms, end_ms);
+            let output_file_path = output_dir_path.join(&segment.file);
+            report.start_file(&output_file_path, i + 1, self.files.len());
+            let pending = PendingOutput::new(&output_file_path);
+            std::fs::write(pending.temp_path(), format_lrc(&lines))?;
+            pending.commit()?;
+
+            info!("Created: {} ({} lines)", output_file_path.display(), lines.len());
+            report.add_output(&output_file_path);
+        }
+
+        info!("Subtitles step completed successfully");
+        Ok(())
+    }
+
+    fn name(&self) -> &str {
+        "Subtitles"
+    }
+}
@@ -1,26 +1,182 @@
-use crate::config::TagFile;
+use crate::album_art::{self, AlbumArt, DEFAULT_JPEG_QUALITY};
+use crate::config::{fill_track_totals, ArtworkFormat, ArtworkOptions, Id3Encoding, Id3Options, Id3Version, ImageType, MusicBrainzIds, TagFile, TagFormat, TagImage, TagVerifyMode, VideoFrameArt};
+use crate::ffmpeg::FfmpegRunner;
+use crate::filename_pattern::FilenamePattern;
+use crate::musicbrainz::MusicBrainzRelease;
+use crate::source_metadata::SourceMetadata;
+use crate::tag_verify::verify_tag;
+use crate::lyrics;
+use crate::cancel;
 use crate::pipeline::Step;
+use crate::run_report::StepReport;
+use crate::workspace::{self, PendingOutput};
 use anyhow::Result;
 use async_trait::async_trait;
-use lofty::{prelude::*, probe::Probe, tag::{Tag, TagItem, ItemValue}, picture::{Picture, PictureType, MimeType}, config::WriteOptions};
+use ffmpeg_sidecar::command::FfmpegCommand;
+use lofty::{prelude::*, file::TaggedFile, probe::Probe, tag::{Tag, TagItem, TagType, ItemValue}, picture::{Picture, PictureType}, config::WriteOptions};
+use lofty::id3::v2::{Frame, Id3v2Tag, SynchronizedTextFrame, SyncTextContentType, TimestampFormat, UniqueFileIdentifierFrame};
+use lofty::TextEncoding;
+use std::collections::HashMap;
 use std::path::Path;
-use tracing::{info, debug, warn};
+use tracing::{info, debug};
+
+/// Step-level settings of a tag step
+#[derive(Debug, Clone, Default)]
+pub struct TagOptions {
+    /// Step defaults over album metadata, used for what no other source sets
+    pub defaults: Option<TagFile>,
+    pub artwork: Option<ArtworkOptions>,
+    pub album_art_from_video: Option<VideoFrameArt>,
+    pub from_filename: Option<FilenamePattern>,
+    /// Video whose container and chapter tags fill values not set otherwise
+    pub from_source: Option<String>,
+    /// Release medium whose tracks are matched to files by track number
+    pub musicbrainz: Option<MusicBrainzRelease>,
+    /// Re-read every tagged file and compare it with the configured values
+    pub verify: Option<TagVerifyMode>,
+    /// Drop every existing field and picture unless a file sets `clear_existing: false`
+    pub clear_existing: bool,
+    /// Existing fields to remove from every file
+    pub remove: Vec<String>,
+    /// Tags other than the one being written to strip from every file
+    pub remove_tag_types: Vec<TagFormat>,
+    /// ID3v2 version and text encoding, and whether to add an ID3v1 tag
+    pub id3: Id3Options,
+}
 
 pub struct TagStep {
     pub input_dir: String,
     pub files: Vec<TagFile>,
+    pub options: TagOptions,
+    pub ffmpeg: FfmpegRunner,
 }
 
 impl TagStep {
-    pub fn new(input_dir: String, files: Vec<TagFile>) -> Self {
-        Self { input_dir, files }
+    pub fn new(input_dir: String, files: Vec<TagFile>, options: TagOptions, ffmpeg: FfmpegRunner) -> Self {
+        Self { input_dir, files, options, ffmpeg }
+    }
+
+    /// Extract the configured video frame to its output image with FFmpeg
+    fn extract_video_frame(&self, working_dir: &Path, frame: &VideoFrameArt, report: &mut StepReport) -> Result<()> {
+        let input_path = working_dir.join(&frame.input);
+        let output_path = working_dir.join(&frame.output);
+
+        if !input_path.exists() {
+            anyhow::bail!("Album art video does not exist: {}", input_path.display());
+        }
+        if let Some(parent) = output_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let mut command = FfmpegCommand::new();
+        command
+            .seek(&frame.timestamp)
+            .input(&input_path)
+            .overwrite()
+            .no_audio()
+            .frames(1);
+        if let Some(crop) = &frame.crop {
+            command.args(["-vf", &format!("crop={}", crop)]);
+        }
+        if album_art::format_for_path(&output_path) == Some(ArtworkFormat::Jpeg) {
+            command.args(["-q:v", "2"]);
+        }
+        let pending = PendingOutput::new(&output_path);
+        command.output(pending.temp_path());
+
+        info!("Extracting album art from {} at {}", frame.input, frame.timestamp);
+        report.add_command(&command);
+        self.ffmpeg.run(&mut command, &format!("extract a frame at {} from {}", frame.timestamp, input_path.display()), &output_path, |_| {})?;
+        pending.commit()?;
+        info!("Created: {}", output_path.display());
+        report.add_output(&output_path);
+
+        Ok(())
+    }
+
+    /// Load and process every album art file of the resolved entries once, keyed by its configured path
+    fn load_album_arts(&self, files: &[TagFile], report: &mut StepReport) -> HashMap<String, AlbumArt> {
+        let mut arts = HashMap::new();
+
+        for image in files.iter().flat_map(|f| f.pictures()) {
+            let album_art_path = &image.file;
+            if arts.contains_key(album_art_path) {
+                continue;
+            }
+
+            let art_path = Path::new(album_art_path);
+            if !art_path.exists() {
+                report.warn(format!("Album art file not found: {}", album_art_path));
+                continue;
+            }
+
+            match album_art::load_album_art(art_path, self.options.artwork.as_ref()) {
+                Ok(art) => {
+                    arts.insert(album_art_path.clone(), art);
+                }
+                Err(e) => report.warn(e.to_string()),
+            }
+        }
+
+        arts
+    }
+
+    /// Write the album art to the `save_as` files in the tagged directory
+    fn save_album_art_files(&self, input_dir_path: &Path, files: &[TagFile], arts: &HashMap<String, AlbumArt>, report: &mut StepReport) -> Result<()> {
+        let Some(options) = &self.options.artwork else {
+            return Ok(());
+        };
+        if options.save_as.is_empty() {
+            return Ok(());
+        }
+
+        let mut used = files.iter()
+            .flat_map(|f| f.pictures())
+            .filter(|image| image.image_type == ImageType::FrontCover && arts.contains_key(&image.file))
+            .map(|image| image.file);
+        let Some(album_art_path) = used.next() else {
+            report.warn(format!("No album art available to write to {}", options.save_as.join(", ")));
+            return Ok(());
+        };
+        if used.any(|p| p != album_art_path) {
+            report.warn(format!("Multiple album art images are used; writing {} as {}", album_art_path, options.save_as.join(", ")));
+        }
+
+        let art = &arts[&album_art_path];
+        let quality = options.quality.unwrap_or(DEFAULT_JPEG_QUALITY);
+        for name in &options.save_as {
+            let output_path = input_dir_path.join(name);
+            let format = album_art::format_for_path(&output_path)
+                .ok_or_else(|| anyhow::anyhow!("Unsupported album art file extension: {}", name))?;
+            let pending = PendingOutput::new(&output_path);
+            std::fs::write(pending.temp_path(), album_art::convert_album_art(art, format, quality)?)?;
+            pending.commit()?;
+            info!("Created: {}", output_path.display());
+            report.add_output(&output_path);
+        }
+
+        Ok(())
     }
 
-    fn apply_metadata_to_file(&self, file_path: &Path, tag_config: &TagFile) -> Result<()> {
+    fn apply_metadata_to_file(&self, file_path: &Path, tag_config: &TagFile, arts: &HashMap<String, AlbumArt>, report: &mut StepReport) -> Result<()> {
         debug!("Applying metadata to: {}", file_path.display());
 
+        // Tags are written to a copy that replaces the file once every write succeeded
+        let pending = PendingOutput::new(file_path);
+        std::fs::copy(file_path, pending.temp_path())?;
+
         // Probe the file to get its type and load it
-        let mut tagged_file = Probe::open(file_path)?.read()?;
+        let mut tagged_file = read_tagged_file(pending.temp_path())?;
+        let duration = tagged_file.properties().duration();
+
+        // Secondary tags are stripped from the file on disk after saving
+        let primary_tag_type = tagged_file.primary_tag_type();
+        let mut removed_tag_types = Vec::new();
+        for tag_type in self.options.remove_tag_types.iter().map(|format| lofty_tag_type(*format)) {
+            if tag_type != primary_tag_type && tagged_file.remove(tag_type).is_some() {
+                removed_tag_types.push(tag_type);
+            }
+        }
 
         // Get or create a tag for the file
         let tag = match tagged_file.primary_tag_mut() {
@@ -33,6 +189,17 @@ impl TagStep {
             }
         };
 
+        // Drop stale values, e.g. from an earlier run or the encoder, before writing
+        if tag_config.clear_existing.unwrap_or(self.options.clear_existing) {
+            tag.clear();
+            debug!("Cleared existing tag");
+        } else {
+            for field in self.options.remove.iter().chain(tag_config.remove.iter().flatten()) {
+                remove_field(tag, field)?;
+                debug!("Removed field: {}", field);
+            }
+        }
+
         // Apply metadata fields
         if let Some(title) = &tag_config.title {
             tag.set_title(title.clone());
@@ -84,59 +251,499 @@ impl TagStep {
             debug!("Set year: {}", year);
         }
 
+        if let Some(date) = &tag_config.date {
+            tag.insert_text(ItemKey::RecordingDate, date.clone());
+            debug!("Set date: {}", date);
+        }
+
+        if let Some(isrc) = &tag_config.isrc {
+            tag.insert_text(ItemKey::Isrc, isrc.clone());
+            debug!("Set ISRC: {}", isrc);
+        }
+
+        if let Some(ids) = &tag_config.musicbrainz {
+            set_musicbrainz_ids(tag, ids);
+        }
+
         if let Some(comment) = &tag_config.comment {
             tag.set_comment(comment.clone());
             debug!("Set comment: {}", comment);
         }
 
-        // Handle album art if specified
-        if let Some(album_art_path) = &tag_config.album_art {
-            let art_path = Path::new(album_art_path);
-            if art_path.exists() {
-                match std::fs::read(art_path) {
-                    Ok(art_data) => {
-                        // Determine MIME type based on file extension
-                        let mime_type = match art_path.extension().and_then(|ext| ext.to_str()) {
-                            Some("jpg") | Some("jpeg") => MimeType::Jpeg,
-                            Some("png") => MimeType::Png,
-                            Some("gif") => MimeType::Gif,
-                            Some("bmp") => MimeType::Bmp,
-                            _ => {
-                                warn!("Unknown image format for album art: {}", album_art_path);
-                                MimeType::Jpeg // Default to JPEG
-                            }
-                        };
+        if let Some(custom) = &tag_config.custom {
+            let tag_type = tag.tag_type();
+            for (key, value) in custom {
+                let item_key = custom_item_key(tag_type, key)?;
+                if tag_type == TagType::Id3v2 && key.len() == 4 {
+                    set_id3v2_user_text(tag, key, Some(value));
+                } else {
+                    // Unknown keys have no generic mapping, so skip the mapping check
+                    tag.insert_unchecked(TagItem::new(item_key, ItemValue::Text(value.clone())));
+                }
+                debug!("Set custom field {}: {}", key, value);
+            }
+        }
 
-                        let picture = Picture::new_unchecked(
-                            PictureType::CoverFront,
-                            Some(mime_type),
-                            None,
-                            art_data,
-                        );
+        if let Some(lyrics_value) = &tag_config.lyrics {
+            let lyrics = lyrics::load_lyrics(lyrics_value)?;
 
-                        tag.set_picture(0, picture);
-                        debug!("Set album art from: {}", album_art_path);
+            if lyrics.is_empty() {
+                report.warn(format!("{}: lyrics have no text and are not written", file_path.display()));
+            } else {
+                // Synchronized lyrics must fit within the track
+                if let Some(last_ms) = lyrics.last_timestamp_ms() {
+                    if u128::from(last_ms) > duration.as_millis() {
+                        anyhow::bail!(
+                            "Lyrics timestamp {:.2}s exceeds the file duration of {:.2}s",
+                            last_ms as f64 / 1000.0, duration.as_secs_f64()
+                        );
                     }
-                    Err(e) => {
-                        warn!("Failed to read album art file {}: {}", album_art_path, e);
+                }
+
+                if tag.tag_type() == TagType::Id3v2 {
+                    // USLT gets the plain text, SYLT the timed lines
+                    tag.insert_text(ItemKey::Lyrics, lyrics.plain_text());
+
+                    if let Some(lines) = &lyrics.lines {
+                        let text: Vec<&str> = lines.iter().map(|line| line.text.as_str()).collect();
+                        let frame = SynchronizedTextFrame::new(
+                            frame_encoding(self.id3_text_encoding(), &text),
+                            *b"XXX",
+                            TimestampFormat::MS,
+                            SyncTextContentType::Lyrics,
+                            None,
+                            lines.iter().map(|line| (line.time_ms, line.text.clone())).collect(),
+                        );
+                        tag.insert_unchecked(TagItem::new(
+                            ItemKey::Unknown("SYLT".to_string()),
+                            ItemValue::Binary(frame.as_bytes()?),
+                        ));
+                        debug!("Set synchronized lyrics: {} lines", lines.len());
                     }
+                } else {
+                    // MP4 and Vorbis comments have no synchronized lyrics field, so keep
+                    // LRC content as-is for players that understand it
+                    tag.insert_text(ItemKey::Lyrics, lyrics.raw.trim().to_string());
+                }
+                debug!("Set lyrics from: {}", lyrics_value);
+            }
+        }
+
+        // Handle album art and extra images if specified
+        let pictures = embedded_pictures(tag_config, arts);
+        if !pictures.is_empty() {
+            if tag.tag_type() == TagType::Mp4Ilst {
+                // MP4 stores every picture as untyped cover art
+                if pictures.iter().any(|(image, _)| image.image_type != ImageType::FrontCover || image.description.is_some()) {
+                    report.warn(format!("{}: MP4 ignores picture types and descriptions; all images are stored as cover art", file_path.display()));
+                }
+                while tag.picture_count() > 0 {
+                    tag.remove_picture(0);
+                }
+            } else {
+                for (image, _) in &pictures {
+                    tag.remove_picture_type(picture_type(image.image_type));
                 }
+            }
+
+            for (image, art) in pictures {
+                let picture = Picture::new_unchecked(
+                    picture_type(image.image_type),
+                    Some(art.mime_type.clone()),
+                    image.description.clone(),
+                    art.data.clone(),
+                );
+
+                tag.push_picture(picture);
+                debug!("Set {:?} picture from: {}", image.image_type, image.file);
+            }
+        }
+
+        if self.options.id3.id3v1 {
+            if tagged_file.file_type().supports_tag_type(TagType::Id3v1) {
+                let id3v1 = id3v1_tag(tagged_file.primary_tag().expect("primary tag was inserted above"));
+                tagged_file.insert_tag(id3v1);
+                debug!("Set ID3v1 tag");
             } else {
-                warn!("Album art file not found: {}", album_art_path);
+                debug!("{:?} files have no ID3v1 tag, skipping it", tagged_file.file_type());
             }
         }
 
         // Save the changes
-        tagged_file.save_to_path(file_path, WriteOptions::default())?;
+        let write_options = WriteOptions::default().use_id3v23(self.options.id3.version == Id3Version::V23);
+        // lofty picks the frame encodings itself and loses the year when saving a
+        // generic tag as ID3v2.3, so those tags are converted and written separately
+        let id3 = &self.options.id3;
+        let id3v2 = if primary_tag_type == TagType::Id3v2 && (id3.encoding.is_some() || id3.version == Id3Version::V23) {
+            tagged_file.remove(TagType::Id3v2).map(Id3v2Tag::from)
+        } else {
+            None
+        };
+        tagged_file.save_to_path(pending.temp_path(), write_options)?;
+        if let Some(id3v2) = id3v2 {
+            let (id3v2, kept_utf16) = encode_id3v2_frames(id3v2, self.id3_text_encoding());
+            if !kept_utf16.is_empty() {
+                report.warn(format!("{}: {} cannot be written as Latin-1, keeping UTF-16", file_path.display(), kept_utf16.join(", ")));
+            }
+            id3v2.save_to_path(pending.temp_path(), write_options)?;
+        }
+        for tag_type in removed_tag_types {
+            tag_type.remove_from_path(pending.temp_path())?;
+            debug!("Removed {:?} tag", tag_type);
+        }
+        pending.commit()?;
         info!("Successfully tagged: {}", file_path.display());
 
         Ok(())
     }
+
+    /// Text encoding of ID3v2 frames: the configured one, or what lofty uses for the version
+    fn id3_text_encoding(&self) -> TextEncoding {
+        match (self.options.id3.encoding, self.options.id3.version) {
+            (Some(Id3Encoding::Latin1), _) => TextEncoding::Latin1,
+            (Some(Id3Encoding::Utf16), _) | (None, Id3Version::V23) => TextEncoding::UTF16,
+            (Some(Id3Encoding::Utf16be), _) => TextEncoding::UTF16BE,
+            (Some(Id3Encoding::Utf8), _) | (None, Id3Version::V24) => TextEncoding::UTF8,
+        }
+    }
+
+    /// Re-open a tagged file and list the configured values that did not survive the save
+    fn verify_file(&self, file_path: &Path, tag_config: &TagFile, arts: &HashMap<String, AlbumArt>) -> Result<Vec<String>> {
+        let tagged_file = read_tagged_file(file_path)?;
+        let Some(tag) = tagged_file.primary_tag() else {
+            return Ok(vec!["no tag found after saving".to_string()]);
+        };
+
+        let mismatches = verify_tag(tag, tag_config, &embedded_pictures(tag_config, arts));
+        debug!("Verified {}: {} mismatches", file_path.display(), mismatches.len());
+
+        Ok(mismatches.iter().map(|m| m.to_string()).collect())
+    }
+}
+
+/// Owner of the ID3v2 UFID frame holding the MusicBrainz recording ID
+const MUSICBRAINZ_UFID_OWNER: &str = "http://musicbrainz.org";
+
+/// Write the MusicBrainz identifiers, with every artist of a multi-artist credit
+fn set_musicbrainz_ids(tag: &mut Tag, ids: &MusicBrainzIds) {
+    let is_id3v2 = tag.tag_type() == TagType::Id3v2;
+
+    let single = [
+        (ItemKey::MusicBrainzReleaseId, &ids.release_id),
+        (ItemKey::MusicBrainzReleaseGroupId, &ids.release_group_id),
+        (ItemKey::MusicBrainzTrackId, &ids.track_id),
+        (ItemKey::MusicBrainzRecordingId, &ids.recording_id),
+    ];
+    for (key, id) in single {
+        let Some(id) = id else {
+            continue;
+        };
+        if is_id3v2 && key == ItemKey::MusicBrainzRecordingId {
+            // ID3v2 keeps the recording ID in a UFID frame, which has no generic key mapping
+            tag.remove_key(&key);
+            let frame = UniqueFileIdentifierFrame::new(MUSICBRAINZ_UFID_OWNER.to_string(), id.clone().into_bytes());
+            tag.insert_unchecked(TagItem::new(
+                ItemKey::Unknown("UFID".to_string()),
+                ItemValue::Binary(frame.as_bytes()),
+            ));
+        } else {
+            tag.insert_text(key, id.clone());
+        }
+    }
+
+    let multiple = [
+        (ItemKey::MusicBrainzReleaseArtistId, &ids.release_artist_ids),
+        (ItemKey::MusicBrainzArtistId, &ids.artist_ids),
+    ];
+    for (key, artist_ids) in multiple {
+        let Some(artist_ids) = artist_ids else {
+            continue;
+        };
+        tag.remove_key(&key);
+        if is_id3v2 {
+            // Repeated TXXX frames overwrite each other; ID3v2.4 separates values with NUL
+            tag.insert_text(key, artist_ids.join("\0"));
+        } else {
+            for id in artist_ids {
+                tag.push(TagItem::new(key.clone(), ItemValue::Text(id.clone())));
+            }
+        }
+    }
+    debug!("Set MusicBrainz IDs: {:?}", ids);
+}
+
+/// Field names accepted by `remove`; any other name is removed as a custom field
+pub const REMOVABLE_FIELDS: &[&str] = &[
+    "title", "artist", "album", "album_artist", "track", "track_total", "disk", "disk_total",
+    "genre", "year", "date", "comment", "lyrics", "isrc", "encoder", "musicbrainz", "pictures",
+];
+
+/// Remove a field such as `comment` or a custom field name from a tag
+fn remove_field(tag: &mut Tag, field: &str) -> Result<()> {
+    let keys = match field {
+        "title" => vec![ItemKey::TrackTitle],
+        "artist" => vec![ItemKey::TrackArtist],
+        "album" => vec![ItemKey::AlbumTitle],
+        "album_artist" => vec![ItemKey::AlbumArtist],
+        "track" => vec![ItemKey::TrackNumber],
+        "track_total" => vec![ItemKey::TrackTotal],
+        "disk" => vec![ItemKey::DiscNumber],
+        "disk_total" => vec![ItemKey::DiscTotal],
+        "genre" => vec![ItemKey::Genre],
+        "year" | "date" => vec![ItemKey::Year, ItemKey::RecordingDate],
+        "comment" => vec![ItemKey::Comment],
+        "lyrics" => vec![ItemKey::Lyrics, ItemKey::Unknown("SYLT".to_string())],
+        "isrc" => vec![ItemKey::Isrc],
+        // FFmpeg writes its version as the encoder (TSSE, ENCODER, ©too)
+        "encoder" => vec![ItemKey::EncoderSoftware, ItemKey::EncoderSettings],
+        "musicbrainz" => vec![
+            ItemKey::MusicBrainzReleaseId,
+            ItemKey::MusicBrainzReleaseGroupId,
+            ItemKey::MusicBrainzReleaseArtistId,
+            ItemKey::MusicBrainzTrackId,
+            ItemKey::MusicBrainzRecordingId,
+            ItemKey::MusicBrainzArtistId,
+        ],
+        "pictures" => {
+            while tag.picture_count() > 0 {
+                tag.remove_picture(0);
+            }
+            return Ok(());
+        }
+        custom if tag.tag_type() == TagType::Id3v2 && custom.len() == 4 => {
+            custom_item_key(TagType::Id3v2, custom)?;
+            set_id3v2_user_text(tag, custom, None);
+            return Ok(());
+        }
+        custom => vec![custom_item_key(tag.tag_type(), custom)?],
+    };
+
+    for key in keys {
+        tag.remove_key(&key);
+    }
+    Ok(())
+}
+
+/// Replace or remove the TXXX frame with a 4-character description
+///
+/// lofty keeps these frames in the ID3v2-specific part of the tag rather than in its
+/// items, so the tag goes through `Id3v2Tag` to avoid adding a second frame.
+fn set_id3v2_user_text(tag: &mut Tag, description: &str, value: Option<&str>) {
+    let mut id3v2 = Id3v2Tag::from(std::mem::replace(tag, Tag::new(TagType::Id3v2)));
+    match value {
+        Some(value) => id3v2.insert_user_text(description.to_string(), value.to_string()),
+        None => id3v2.remove_user_text(description),
+    };
+    *tag = Tag::from(id3v2);
+}
+
+/// The fields an ID3v1 tag can hold, copied from the tag being written
+///
+/// lofty writes ID3v1 text as UTF-8 bytes that players read as Latin-1, so
+/// anything outside ASCII becomes `?`.
+fn id3v1_tag(tag: &Tag) -> Tag {
+    let ascii = |value: &str| -> String {
+        value.chars().map(|c| if c.is_ascii() { c } else { '?' }).collect()
+    };
+
+    let mut id3v1 = Tag::new(TagType::Id3v1);
+    if let Some(title) = tag.title() {
+        id3v1.set_title(ascii(&title));
+    }
+    if let Some(artist) = tag.artist() {
+        id3v1.set_artist(ascii(&artist));
+    }
+    if let Some(album) = tag.album() {
+        id3v1.set_album(ascii(&album));
+    }
+    if let Some(year) = tag.year() {
+        // `set_year` would store a recording date, which ID3v1 does not write
+        id3v1.insert_text(ItemKey::Year, year.to_string());
+    }
+    if let Some(comment) = tag.comment() {
+        id3v1.set_comment(ascii(&comment));
+    }
+    if let Some(track) = tag.track() {
+        id3v1.set_track(track);
+    }
+    if let Some(genre) = tag.genre() {
+        id3v1.set_genre(genre.to_string());
+    }
+    id3v1
+}
+
+/// Latin-1 cannot hold every character, so text outside it falls back to UTF-16
+fn frame_encoding(encoding: TextEncoding, text: &[&str]) -> TextEncoding {
+    let fits = |value: &&str| value.chars().all(|c| u32::from(c) <= 0xFF);
+    if encoding == TextEncoding::Latin1 && !text.iter().all(fits) {
+        TextEncoding::UTF16
+    } else {
+        encoding
+    }
+}
+
+/// Set the text encoding of every frame that has one
+///
+/// Also returns the IDs of frames that stayed UTF-16 because Latin-1 could not hold their text.
+fn encode_id3v2_frames(tag: Id3v2Tag, encoding: TextEncoding) -> (Id3v2Tag, Vec<String>) {
+    let mut encoded = Id3v2Tag::default();
+    encoded.set_flags(*tag.flags());
+    let mut kept_utf16 = Vec::new();
+
+    for mut frame in tag {
+        let id = frame.id_str().to_string();
+        let frame_encoding = match &mut frame {
+            Frame::Text(text) => {
+                text.encoding = frame_encoding(encoding, &[&text.value]);
+                Some(text.encoding)
+            }
+            Frame::UserText(text) => {
+                text.encoding = frame_encoding(encoding, &[&text.description, &text.content]);
+                Some(text.encoding)
+            }
+            Frame::Comment(comment) => {
+                comment.encoding = frame_encoding(encoding, &[&comment.description, &comment.content]);
+                Some(comment.encoding)
+            }
+            Frame::UnsynchronizedText(text) => {
+                text.encoding = frame_encoding(encoding, &[&text.description, &text.content]);
+                Some(text.encoding)
+            }
+            Frame::Picture(picture) => {
+                picture.encoding = frame_encoding(encoding, &[picture.picture.description().unwrap_or_default()]);
+                Some(picture.encoding)
+            }
+            Frame::KeyValue(pairs) => {
+                let text: Vec<&str> = pairs.key_value_pairs.iter()
+                    .flat_map(|(key, value)| [key.as_str(), value.as_str()])
+                    .collect();
+                pairs.encoding = frame_encoding(encoding, &text);
+                Some(pairs.encoding)
+            }
+            Frame::Timestamp(timestamp) => {
+                timestamp.encoding = encoding;
+                Some(timestamp.encoding)
+            }
+            _ => None,
+        };
+        if frame_encoding.is_some_and(|e| e != encoding) {
+            kept_utf16.push(id);
+        }
+        encoded.insert(frame);
+    }
+
+    (encoded, kept_utf16)
+}
+
+/// Map a configured tag format onto the lofty tag type
+fn lofty_tag_type(format: TagFormat) -> TagType {
+    match format {
+        TagFormat::Id3v1 => TagType::Id3v1,
+        TagFormat::Id3v2 => TagType::Id3v2,
+        TagFormat::Ape => TagType::Ape,
+        TagFormat::RiffInfo => TagType::RiffInfo,
+    }
+}
+
+/// Open a file with lofty, reading its tags and audio properties
+pub fn read_tagged_file(path: &Path) -> Result<TaggedFile> {
+    Ok(Probe::open(path)?.read()?)
+}
+
+/// The pictures of an entry whose image could be loaded
+fn embedded_pictures<'a>(tag_config: &TagFile, arts: &'a HashMap<String, AlbumArt>) -> Vec<(TagImage, &'a AlbumArt)> {
+    tag_config.pictures().into_iter()
+        .filter_map(|image| arts.get(&image.file).map(|art| (image, art)))
+        .collect()
+}
+
+/// Map a configured image type onto the lofty picture type
+pub fn picture_type(image_type: ImageType) -> PictureType {
+    match image_type {
+        ImageType::Other => PictureType::Other,
+        ImageType::Icon => PictureType::Icon,
+        ImageType::OtherIcon => PictureType::OtherIcon,
+        ImageType::FrontCover => PictureType::CoverFront,
+        ImageType::BackCover => PictureType::CoverBack,
+        ImageType::Leaflet => PictureType::Leaflet,
+        ImageType::Media => PictureType::Media,
+        ImageType::LeadArtist => PictureType::LeadArtist,
+        ImageType::Artist => PictureType::Artist,
+        ImageType::Conductor => PictureType::Conductor,
+        ImageType::Band => PictureType::Band,
+        ImageType::Composer => PictureType::Composer,
+        ImageType::Lyricist => PictureType::Lyricist,
+        ImageType::RecordingLocation => PictureType::RecordingLocation,
+        ImageType::DuringRecording => PictureType::DuringRecording,
+        ImageType::DuringPerformance => PictureType::DuringPerformance,
+        ImageType::ScreenCapture => PictureType::ScreenCapture,
+        ImageType::Illustration => PictureType::Illustration,
+        ImageType::BandLogo => PictureType::BandLogo,
+        ImageType::PublisherLogo => PictureType::PublisherLogo,
+    }
+}
+
+/// Map a custom field name onto the item key used by the given tag type
+///
+/// - ID3v2: the name becomes the description of a TXXX frame
+/// - MP4: the name becomes a `----:com.apple.iTunes:<name>` freeform atom
+///   (a full `----:<mean>:<name>` identifier is used as-is)
+/// - Vorbis comments / APE: the name is used as the field name
+pub fn custom_item_key(tag_type: TagType, key: &str) -> Result<ItemKey> {
+    if key.is_empty() {
+        anyhow::bail!("Custom tag key must not be empty");
+    }
+
+    let mapped = match tag_type {
+        TagType::Id3v2 => {
+            // Keys that look like frame IDs would be written as frames instead of TXXX
+            if key.len() == 4 && key.bytes().all(|b| b.is_ascii_uppercase() || b.is_ascii_digit()) {
+                anyhow::bail!("Custom tag key '{}' is ambiguous in ID3v2: 4 uppercase letters or digits are reserved for frame IDs", key);
+            }
+            if key.contains('\0') {
+                anyhow::bail!("Custom tag key '{}' contains a NUL character, which is not allowed in ID3v2", key);
+            }
+            key.to_string()
+        }
+        TagType::Mp4Ilst => {
+            if let Some(freeform) = key.strip_prefix("----:") {
+                let parts: Vec<&str> = freeform.split(':').collect();
+                if parts.len() != 2 || parts.iter().any(|p| p.is_empty()) {
+                    anyhow::bail!("Custom tag key '{}' is not a valid MP4 freeform identifier. Expected ----:<mean>:<name>", key);
+                }
+                key.to_string()
+            } else {
+                if key.contains(':') {
+                    anyhow::bail!("Custom tag key '{}' contains ':', which is not allowed in MP4 freeform atom names", key);
+                }
+                format!("----:com.apple.iTunes:{}", key)
+            }
+        }
+        TagType::VorbisComments => {
+            // Field names are restricted to printable ASCII 0x20-0x7D excluding '='
+            if !key.bytes().all(|b| (0x20..=0x7D).contains(&b) && b != b'=') {
+                anyhow::bail!("Custom tag key '{}' is not a valid Vorbis comment field name (printable ASCII without '=', '~', '\x7F')", key);
+            }
+            key.to_ascii_uppercase()
+        }
+        TagType::Ape => {
+            // APE keys are 2-255 printable ASCII characters
+            if key.len() < 2 || key.len() > 255 || !key.bytes().all(|b| (0x20..=0x7E).contains(&b)) {
+                anyhow::bail!("Custom tag key '{}' is not a valid APE item key (2-255 printable ASCII characters)", key);
+            }
+            key.to_string()
+        }
+        other => {
+            anyhow::bail!("Custom tag keys are not supported for {:?} tags", other);
+        }
+    };
+
+    Ok(ItemKey::Unknown(mapped))
 }
 
 #[async_trait]
 impl Step for TagStep {
-    async fn execute(&self, working_dir: &Path) -> Result<()> {
+    async fn execute(&self, working_dir: &Path, report: &mut StepReport) -> Result<()> {
         info!("Executing Tag step with {} files", self.files.len());
 
         let input_dir_path = working_dir.join(&self.input_dir);
@@ -148,17 +755,31 @@ impl Step for TagStep {
             anyhow::bail!("Input directory does not exist: {}", input_dir_path.display());
         }
 
-        // Process each file configuration
-        for (i, tag_config) in self.files.iter().enumerate() {
-            info!("Processing file {}/{}: {}", i + 1, self.files.len(), tag_config.file);
+        if let Some(frame) = &self.options.album_art_from_video {
+            self.extract_video_frame(working_dir, frame, report)?;
+        }
+
+        let source_metadata = match &self.options.from_source {
+            Some(source) => {
+                let metadata = SourceMetadata::probe(&working_dir.join(source))?;
+                info!("Read source metadata from {} ({} chapters)", source, metadata.chapters.len());
+                Some(metadata)
+            }
+            None => None,
+        };
+
+        let mut failed_verifications = Vec::new();
 
+        // Find the files of each entry first so progress can count them all
+        let mut targets = Vec::new();
+        for tag_config in &self.files {
             // Find matching files (support wildcards)
-            let matching_files = if tag_config.file.contains('*') {
+            let matching_files: Vec<_> = if tag_config.file.contains('*') {
                 // Use glob pattern matching
                 let pattern_path = input_dir_path.join(&tag_config.file);
                 let pattern_str = pattern_path.to_string_lossy();
                 
-                glob::glob(&pattern_str)?
+                workspace::glob(&pattern_str)?
                     .collect::<Result<Vec<_>, _>>()?
                     .into_iter()
                     .filter(|p| p.is_file())
@@ -169,30 +790,96 @@ impl Step for TagStep {
                 if file_path.exists() && file_path.is_file() {
                     vec![file_path]
                 } else {
-                    warn!("File not found: {}", file_path.display());
+                    report.warn(format!("File not found: {}", file_path.display()));
                     continue;
                 }
             };
 
             if matching_files.is_empty() {
-                warn!("No files found matching pattern: {}", tag_config.file);
+                report.warn(format!("No files found matching pattern: {}", tag_config.file));
                 continue;
             }
+            targets.extend(matching_files.into_iter().map(|file_path| (tag_config, file_path)));
+        }
 
-            // Apply metadata to each matching file
-            for file_path in matching_files {
-                match self.apply_metadata_to_file(&file_path, tag_config) {
-                    Ok(()) => {
-                        debug!("Successfully tagged: {}", file_path.display());
-                    }
-                    Err(e) => {
-                        warn!("Failed to tag file {}: {}", file_path.display(), e);
-                        // Continue with other files instead of failing the entire step
+        // Merge the values of every source first; track totals count the resolved files
+        let mut paths = Vec::with_capacity(targets.len());
+        let mut resolved = Vec::with_capacity(targets.len());
+        for (tag_config, file_path) in targets {
+            // Explicit values take priority over fields parsed from the file name
+            let extracted = self.options.from_filename.as_ref().and_then(|pattern| {
+                let file_name = file_path.file_name()?.to_string_lossy();
+                let extracted = pattern.extract(&file_name);
+                if extracted.is_none() {
+                    report.warn(format!("File name '{}' does not match pattern '{}'", file_name, pattern.as_str()));
+                }
+                extracted
+            });
+            let mut file_config = match &extracted {
+                Some(extracted) => tag_config.merged_with(extracted),
+                None => tag_config.clone(),
+            };
+            if let Some(release) = &self.options.musicbrainz {
+                match release.for_track(file_config.track) {
+                    Some(track) => file_config = file_config.merged_with(track),
+                    None => report.warn(format!("{}: no MusicBrainz track for track number {:?}", file_path.display(), file_config.track)),
+                }
+            }
+            // Source tags only fill what is still missing; chapters map onto track numbers
+            if let Some(metadata) = &source_metadata {
+                file_config = file_config.merged_with(&metadata.for_track(file_config.track));
+            }
+            if let Some(defaults) = &self.options.defaults {
+                file_config = file_config.merged_with(defaults);
+            }
+            if let Some(frame) = &self.options.album_art_from_video {
+                frame.apply_to(std::slice::from_mut(&mut file_config));
+            }
+            resolved.push(file_config);
+            paths.push(file_path);
+        }
+        fill_track_totals(&mut resolved);
+        // Art can come from defaults or the video frame, so it is loaded for the resolved entries
+        let arts = self.load_album_arts(&resolved, report);
+
+        // Apply metadata to each matching file
+        let total = paths.len();
+        for (i, (file_path, file_config)) in paths.into_iter().zip(&resolved).enumerate() {
+            cancel::check()?;
+            info!("Processing file {}/{}: {}", i + 1, total, file_path.display());
+            report.start_file(&file_path, i + 1, total);
+
+            match self.apply_metadata_to_file(&file_path, file_config, &arts, report) {
+                Ok(()) => {
+                    debug!("Successfully tagged: {}", file_path.display());
+
+                    if let Some(mode) = self.options.verify {
+                        let mismatches = self.verify_file(&file_path, file_config, &arts)?;
+                        if !mismatches.is_empty() {
+                            let diff = format!("{}:\n  {}", file_path.display(), mismatches.join("\n  "));
+                            match mode {
+                                TagVerifyMode::Warn => report.warn(format!("Tags did not read back as written for {}", diff)),
+                                TagVerifyMode::Fail => failed_verifications.push(diff),
+                            }
+                        }
                     }
                 }
+                Err(e) => {
+                    report.warn(format!("Failed to tag file {}: {}", file_path.display(), e));
+                    // Continue with other files instead of failing the entire step
+                }
             }
         }
 
+        if !failed_verifications.is_empty() {
+            anyhow::bail!(
+                "Tag verification failed for {} files:\n{}",
+                failed_verifications.len(), failed_verifications.join("\n")
+            );
+        }
+
+        self.save_album_art_files(&input_dir_path, &resolved, &arts, report)?;
+
         info!("Tag step completed successfully");
         Ok(())
     }
@@ -200,4 +887,197 @@ impl Step for TagStep {
     fn name(&self) -> &str {
         "Tag"
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    /// A few silent MPEG-1 Layer III frames, enough for lofty to read and tag
+    fn write_mp3(path: &Path) {
+        let mut frame = vec![0xFF, 0xFB, 0x90, 0x64];
+        frame.resize(417, 0);
+        std::fs::write(path, frame.repeat(8)).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_skips_stale_incomplete_outputs() {
+        let dir = TempDir::new().unwrap();
+        std::fs::create_dir(dir.path().join("audio")).unwrap();
+        write_mp3(&dir.path().join("audio/01.mp3"));
+        // A partial left behind by a crash while tagging 02.mp3
+        let stale = PendingOutput::new(&dir.path().join("audio/02.mp3")).temp_path().to_path_buf();
+        std::fs::write(&stale, "partial").unwrap();
+
+        let step = TagStep::new(
+            "audio".to_string(),
+            vec![TagFile { file: "*.mp3".to_string(), title: Some("Intro".to_string()), ..Default::default() }],
+            TagOptions::default(),
+            FfmpegRunner::new(None),
+        );
+        let mut report = StepReport::new("Tag");
+        step.execute(dir.path(), &mut report).await.unwrap();
+
+        assert!(report.warnings.is_empty(), "{:?}", report.warnings);
+        assert_eq!(std::fs::read_to_string(&stale).unwrap(), "partial");
+        let tagged = Probe::open(dir.path().join("audio/01.mp3")).unwrap().read().unwrap();
+        assert_eq!(tagged.primary_tag().unwrap().title().as_deref(), Some("Intro"));
+    }
+
+    #[tokio::test]
+    async fn test_track_total_counts_merged_track_numbers() {
+        let dir = TempDir::new().unwrap();
+        write_mp3(&dir.path().join("01 - Intro.mp3"));
+        write_mp3(&dir.path().join("02 - Outro.mp3"));
+
+        // Track numbers only come from the file names
+        let step = TagStep::new(
+            ".".to_string(),
+            vec![TagFile { file: "*.mp3".to_string(), ..Default::default() }],
+            TagOptions {
+                from_filename: Some(FilenamePattern::parse("{track} - {title}").unwrap()),
+                ..Default::default()
+            },
+            FfmpegRunner::new(None),
+        );
+        let mut report = StepReport::new("Tag");
+        step.execute(dir.path(), &mut report).await.unwrap();
+
+        let tagged = Probe::open(dir.path().join("02 - Outro.mp3")).unwrap().read().unwrap();
+        let tag = tagged.primary_tag().unwrap();
+        assert_eq!(tag.track(), Some(2));
+        assert_eq!(tag.track_total(), Some(2));
+    }
+
+    #[tokio::test]
+    async fn test_defaults_only_fill_what_other_sources_leave_unset() {
+        let dir = TempDir::new().unwrap();
+        write_mp3(&dir.path().join("01 - Band - Intro.mp3"));
+
+        let step = TagStep::new(
+            ".".to_string(),
+            vec![TagFile { file: "*.mp3".to_string(), ..Default::default() }],
+            TagOptions {
+                defaults: Some(TagFile {
+                    artist: Some("Various Artists".to_string()),
+                    genre: Some("Live".to_string()),
+                    ..Default::default()
+                }),
+                from_filename: Some(FilenamePattern::parse("{track} - {artist} - {title}").unwrap()),
+                ..Default::default()
+            },
+            FfmpegRunner::new(None),
+        );
+        let mut report = StepReport::new("Tag");
+        step.execute(dir.path(), &mut report).await.unwrap();
+
+        let tagged = Probe::open(dir.path().join("01 - Band - Intro.mp3")).unwrap().read().unwrap();
+        let tag = tagged.primary_tag().unwrap();
+        assert_eq!(tag.artist().as_deref(), Some("Band"));
+        assert_eq!(tag.genre().as_deref(), Some("Live"));
+    }
+
+    #[tokio::test]
+    async fn test_skips_lyrics_without_text() {
+        let dir = TempDir::new().unwrap();
+        write_mp3(&dir.path().join("01.mp3"));
+
+        let step = TagStep::new(
+            ".".to_string(),
+            vec![TagFile {
+                file: "01.mp3".to_string(),
+                title: Some("Intro".to_string()),
+                lyrics: Some("[ar:Band]\n[00:00.00]".to_string()),
+                ..Default::default()
+            }],
+            TagOptions::default(),
+            FfmpegRunner::new(None),
+        );
+        let mut report = StepReport::new("Tag");
+        step.execute(dir.path(), &mut report).await.unwrap();
+
+        assert_eq!(report.warnings.len(), 1);
+        assert!(report.warnings[0].contains("lyrics have no text"));
+        let tagged = Probe::open(dir.path().join("01.mp3")).unwrap().read().unwrap();
+        let tag = tagged.primary_tag().unwrap();
+        assert_eq!(tag.title().as_deref(), Some("Intro"));
+        assert!(tag.get(&ItemKey::Lyrics).is_none());
+        assert!(tag.get(&ItemKey::Unknown("SYLT".to_string())).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_embeds_album_art_set_only_in_defaults() {
+        let dir = TempDir::new().unwrap();
+        write_mp3(&dir.path().join("01.mp3"));
+        let cover = dir.path().join("cover.png");
+        image::RgbImage::new(4, 4).save(&cover).unwrap();
+
+        let step = TagStep::new(
+            ".".to_string(),
+            vec![TagFile { file: "01.mp3".to_string(), ..Default::default() }],
+            TagOptions {
+                defaults: Some(TagFile {
+                    album_art: Some(cover.to_string_lossy().into_owned()),
+                    ..Default::default()
+                }),
+                artwork: Some(ArtworkOptions {
+                    save_as: vec!["folder.jpg".to_string()],
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+            FfmpegRunner::new(None),
+        );
+        let mut report = StepReport::new("Tag");
+        step.execute(dir.path(), &mut report).await.unwrap();
+
+        assert!(report.warnings.is_empty(), "{:?}", report.warnings);
+        let tagged = Probe::open(dir.path().join("01.mp3")).unwrap().read().unwrap();
+        let pictures = tagged.primary_tag().unwrap().pictures();
+        assert_eq!(pictures.len(), 1);
+        assert_eq!(pictures[0].pic_type(), PictureType::CoverFront);
+        assert!(dir.path().join("folder.jpg").exists());
+    }
+
+    #[tokio::test]
+    async fn test_custom_keys_that_are_not_frame_ids_use_txxx() {
+        let dir = TempDir::new().unwrap();
+        write_mp3(&dir.path().join("01.mp3"));
+
+        let step = TagStep::new(
+            ".".to_string(),
+            vec![TagFile {
+                file: "01.mp3".to_string(),
+                title: Some("Intro".to_string()),
+                track: Some(1),
+                year: Some(2024),
+                custom: Some([("mood".to_string(), "Calm".to_string())].into()),
+                ..Default::default()
+            }],
+            TagOptions { verify: Some(TagVerifyMode::Fail), ..Default::default() },
+            FfmpegRunner::new(None),
+        );
+        let mut report = StepReport::new("Tag");
+        step.execute(dir.path(), &mut report).await.unwrap();
+        // Tagging again replaces the frame rather than adding a second one
+        step.execute(dir.path(), &mut report).await.unwrap();
+        let raw = std::fs::read(dir.path().join("01.mp3")).unwrap();
+        assert_eq!(raw.windows(5).filter(|window| window == b"mood\0").count(), 1);
+
+        let step = TagStep::new(
+            ".".to_string(),
+            vec![TagFile { file: "01.mp3".to_string(), remove: Some(vec!["mood".to_string()]), ..Default::default() }],
+            TagOptions::default(),
+            FfmpegRunner::new(None),
+        );
+        step.execute(dir.path(), &mut report).await.unwrap();
+        let raw = std::fs::read(dir.path().join("01.mp3")).unwrap();
+        assert!(!raw.windows(5).any(|window| window == b"mood\0"));
+
+        assert!(report.warnings.is_empty(), "{:?}", report.warnings);
+        assert!(custom_item_key(TagType::Id3v2, "Tempo").is_ok());
+        assert!(custom_item_key(TagType::Id3v2, "TBPM").is_err());
+        assert!(custom_item_key(TagType::Id3v2, "MOO1").is_err());
+    }
+}
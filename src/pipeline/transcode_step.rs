@@ -1,10 +1,12 @@
-use crate::ffmpeg::EncoderAvailability;
+use crate::ffmpeg::{EncoderAvailability, FfmpegRunner};
 use crate::pipeline::Step;
+use crate::workspace::{self, PendingOutput};
+use crate::run_report::StepReport;
 use anyhow::Result;
 use async_trait::async_trait;
 use ffmpeg_sidecar::command::FfmpegCommand;
 use std::path::Path;
-use tracing::{info, debug, warn};
+use tracing::{info, debug};
 
 pub struct TranscodeStep {
     pub input_dir: String,
@@ -14,9 +16,11 @@ pub struct TranscodeStep {
     pub bitrate: Option<String>,
     pub bit_depth: Option<u8>,
     pub encoder_availability: EncoderAvailability,
+    pub ffmpeg: FfmpegRunner,
 }
 
 impl TranscodeStep {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         input_dir: String,
         output_dir: String,
@@ -25,6 +29,7 @@ impl TranscodeStep {
         bitrate: Option<String>,
         bit_depth: Option<u8>,
         encoder_availability: EncoderAvailability,
+        ffmpeg: FfmpegRunner,
     ) -> Self {
         Self {
             input_dir,
@@ -34,6 +39,7 @@ impl TranscodeStep {
             bitrate,
             bit_depth,
             encoder_availability,
+            ffmpeg,
         }
     }
 
@@ -108,7 +114,7 @@ impl TranscodeStep {
 
 #[async_trait]
 impl Step for TranscodeStep {
-    async fn execute(&self, working_dir: &Path) -> Result<()> {
+    async fn execute(&self, working_dir: &Path, report: &mut StepReport) -> Result<()> {
         info!(
             "Executing Transcode step: {} -> {} (format: {}, bitrate: {:?}, bit depth: {:?})",
             self.input_dir, self.output_dir, self.format, self.bitrate, self.bit_depth
@@ -134,17 +140,16 @@ impl Step for TranscodeStep {
         // Get codec arguments
         let codec_args = self.get_codec_args()?;
 
-        // Process each file
-        for (i, file_pattern) in self.files.iter().enumerate() {
-            info!("Processing file {}/{}: {}", i + 1, self.files.len(), file_pattern);
-
+        // Find the files of each pattern first so progress can count them all
+        let mut input_files = Vec::new();
+        for file_pattern in &self.files {
             // Find matching files (support wildcards)
-            let matching_files = if file_pattern.contains('*') {
+            let matching_files: Vec<_> = if file_pattern.contains('*') {
                 // Use glob pattern matching
                 let pattern_path = input_dir_path.join(file_pattern);
                 let pattern_str = pattern_path.to_string_lossy();
                 
-                glob::glob(&pattern_str)?
+                workspace::glob(&pattern_str)?
                     .collect::<Result<Vec<_>, _>>()?
                     .into_iter()
                     .filter(|p| p.is_file())
@@ -155,53 +160,59 @@ impl Step for TranscodeStep {
                 if file_path.exists() && file_path.is_file() {
                     vec![file_path]
                 } else {
-                    warn!("File not found: {}", file_path.display());
+                    report.warn(format!("File not found: {}", file_path.display()));
                     continue;
                 }
             };
 
             if matching_files.is_empty() {
-                warn!("No files found matching pattern: {}", file_pattern);
+                report.warn(format!("No files found matching pattern: {}", file_pattern));
                 continue;
             }
+            input_files.extend(matching_files);
+        }
 
-            // Transcode each matching file
-            for input_file_path in matching_files {
-                let input_filename = input_file_path
-                    .file_name()
-                    .and_then(|n| n.to_str())
-                    .unwrap_or("unknown");
-
-                let output_filename = self.get_output_filename(input_filename);
-                let output_file_path = output_dir_path.join(&output_filename);
-
-                debug!("Transcoding: {} -> {}", input_file_path.display(), output_file_path.display());
+        // Transcode each matching file
+        for (i, input_file_path) in input_files.iter().enumerate() {
+            let input_filename = input_file_path
+                .file_name()
+                .and_then(|n| n.to_str())
+                .unwrap_or("unknown");
 
-                // Build FFmpeg command
-                let mut command = FfmpegCommand::new();
-                command
-                    .input(input_file_path.to_string_lossy())
-                    .overwrite();
+            info!("Processing file {}/{}: {}", i + 1, input_files.len(), input_filename);
 
-                // Add codec arguments
-                for arg in &codec_args {
-                    command.arg(arg);
-                }
+            let output_filename = self.get_output_filename(input_filename);
+            let output_file_path = output_dir_path.join(&output_filename);
 
-                command.output(output_file_path.to_string_lossy());
+            debug!("Transcoding: {} -> {}", input_file_path.display(), output_file_path.display());
 
-                // Execute FFmpeg command
-                info!("Running FFmpeg transcode for: {}", input_filename);
-                let mut child = command.spawn()?;
-                let result = child.wait()?;
-                
-                if !result.success() {
-                    anyhow::bail!("FFmpeg failed for file: {}, exit code: {:?}", input_filename, result.code());
-                }
+            // Build FFmpeg command
+            let mut command = FfmpegCommand::new();
+            command
+                .input(input_file_path)
+                .overwrite();
 
-                let file_size = std::fs::metadata(&output_file_path)?.len();
-                info!("Created: {} ({} bytes)", output_file_path.display(), file_size);
+            // Add codec arguments
+            for arg in &codec_args {
+                command.arg(arg);
             }
+
+            // Written under a temporary name until FFmpeg succeeds
+            let pending = PendingOutput::new(&output_file_path);
+            command.output(pending.temp_path());
+
+            // Execute FFmpeg command
+            info!("Running FFmpeg transcode for: {}", input_filename);
+            report.add_command(&command);
+            report.start_file(&output_file_path, i + 1, input_files.len());
+            self.ffmpeg.run(&mut command, &format!("transcode {}", input_filename), &output_file_path, |progress| {
+                report.file_progress(&output_file_path, progress);
+            })?;
+            pending.commit()?;
+
+            let file_size = std::fs::metadata(&output_file_path)?.len();
+            info!("Created: {} ({} bytes)", output_file_path.display(), file_size);
+            report.add_output(&output_file_path);
         }
 
         info!("Transcode step completed successfully");
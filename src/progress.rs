@@ -0,0 +1,292 @@
+use crate::run_report::StepStatus;
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use serde::Serialize;
+use std::fmt::Debug;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// How progress is shown while the pipeline runs
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum ProgressMode {
+    /// Progress bars on the terminal
+    #[default]
+    Bars,
+    /// Newline-delimited JSON events on stdout
+    Json,
+}
+
+impl ProgressMode {
+    pub fn reporter(self) -> Arc<dyn ProgressReporter> {
+        match self {
+            ProgressMode::Bars => Arc::new(BarReporter::default()),
+            ProgressMode::Json => Arc::new(JsonReporter),
+        }
+    }
+}
+
+/// Something that happened while the pipeline runs
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum ProgressEvent<'a> {
+    StepStarted {
+        step: &'a str,
+        /// 1-based position of the step in the pipeline
+        index: usize,
+        total: usize,
+    },
+    StepFinished {
+        step: &'a str,
+        index: usize,
+        status: StepStatus,
+        duration_seconds: f64,
+    },
+    /// The step moves on to the next of its files
+    FileStarted {
+        step: &'a str,
+        file: &'a Path,
+        /// 1-based position of the file among those the step processes
+        index: usize,
+        total: usize,
+    },
+    FileProgress {
+        step: &'a str,
+        file: &'a Path,
+        #[serde(flatten)]
+        progress: &'a FileProgress,
+    },
+    /// An output file was written
+    FileCreated {
+        step: &'a str,
+        file: &'a Path,
+    },
+    Warning {
+        step: &'a str,
+        message: &'a str,
+    },
+    RunFinished {
+        success: bool,
+        duration_seconds: f64,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        error: Option<&'a str>,
+    },
+}
+
+/// How far a step is with the file it is writing
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct FileProgress {
+    /// Media time processed so far
+    pub time_seconds: f64,
+    /// Unknown until the length of the input is known
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub percent: Option<f64>,
+    /// Processing speed as a multiple of real time
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub speed: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub eta_seconds: Option<f64>,
+    /// Samples per channel written, for steps that copy samples themselves
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub samples: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total_samples: Option<u64>,
+}
+
+impl FileProgress {
+    /// Progress of an FFmpeg run from the media time it reached, its speed and the input length
+    pub fn from_ffmpeg(time_seconds: f64, speed: Option<f64>, total_seconds: Option<f64>) -> Self {
+        let speed = speed.filter(|s| *s > 0.0);
+        let percent = total_seconds
+            .filter(|total| *total > 0.0)
+            .map(|total| (time_seconds / total * 100.0).min(100.0));
+        let eta_seconds = match (total_seconds, speed) {
+            (Some(total), Some(speed)) => Some((total - time_seconds).max(0.0) / speed),
+            _ => None,
+        };
+        Self { time_seconds, percent, speed, eta_seconds, ..Default::default() }
+    }
+
+    /// Progress of a copy that has written `samples` of `total_samples` samples per channel
+    pub fn from_samples(samples: u64, total_samples: u64, sample_rate: u32) -> Self {
+        let percent = match total_samples {
+            0 => 100.0,
+            total => (samples as f64 / total as f64 * 100.0).min(100.0),
+        };
+        Self {
+            time_seconds: samples as f64 / sample_rate as f64,
+            percent: Some(percent),
+            samples: Some(samples),
+            total_samples: Some(total_samples),
+            ..Default::default()
+        }
+    }
+}
+
+/// Receives progress events from the executor and every step
+pub trait ProgressReporter: Send + Sync + Debug {
+    fn report(&self, event: &ProgressEvent);
+}
+
+/// Writes each event as a line of JSON to stdout
+#[derive(Debug)]
+pub struct JsonReporter;
+
+impl ProgressReporter for JsonReporter {
+    fn report(&self, event: &ProgressEvent) {
+        let Ok(line) = serde_json::to_string(event) else {
+            return;
+        };
+        let mut stdout = std::io::stdout().lock();
+        let _ = writeln!(stdout, "{}", line);
+        let _ = stdout.flush();
+    }
+}
+
+/// Shows how many files a step has processed and how far it is with the current one;
+/// warnings and step changes are left to the log
+#[derive(Debug, Default)]
+pub struct BarReporter {
+    bars: Mutex<Bars>,
+}
+
+#[derive(Debug, Default)]
+struct Bars {
+    multi: MultiProgress,
+    /// Files done out of the files of the step, shown for steps with more than one file
+    overall: Option<ProgressBar>,
+    /// Percentage of the file being written
+    current: Option<(PathBuf, ProgressBar)>,
+}
+
+impl Bars {
+    fn overall_bar(&mut self, total: usize) -> &ProgressBar {
+        self.overall.get_or_insert_with(|| {
+            let bar = self.multi.insert(0, ProgressBar::new(total as u64));
+            bar.set_style(
+                ProgressStyle::default_bar()
+                    .template("  [{elapsed_precise}] [{bar:40.green/white}] {pos}/{len} files {msg}")
+                    .unwrap()
+                    .progress_chars("#>-")
+            );
+            bar
+        })
+    }
+
+    fn current_bar(&mut self, file: &Path) -> &ProgressBar {
+        if self.current.as_ref().is_none_or(|(path, _)| path != file) {
+            self.finish_current();
+            let bar = self.multi.add(ProgressBar::new(100));
+            bar.set_style(
+                ProgressStyle::default_bar()
+                    .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}% {msg}")
+                    .unwrap()
+                    .progress_chars("#>-")
+            );
+            bar.enable_steady_tick(Duration::from_millis(100));
+            self.current = Some((file.to_path_buf(), bar));
+        }
+        &self.current.as_ref().unwrap().1
+    }
+
+    fn finish_current(&mut self) {
+        if let Some((_, bar)) = self.current.take() {
+            bar.finish_and_clear();
+        }
+    }
+
+    fn finish(&mut self) {
+        self.finish_current();
+        if let Some(bar) = self.overall.take() {
+            bar.finish_and_clear();
+        }
+    }
+}
+
+impl ProgressReporter for BarReporter {
+    fn report(&self, event: &ProgressEvent) {
+        let mut bars = self.bars.lock().unwrap();
+        match event {
+            ProgressEvent::FileStarted { file, index, total, .. } => {
+                bars.finish_current();
+                if *total > 1 {
+                    let bar = bars.overall_bar(*total);
+                    bar.set_position(*index as u64 - 1);
+                    bar.set_message(file.file_name().unwrap_or_default().to_string_lossy().into_owned());
+                }
+            }
+            ProgressEvent::FileProgress { file, progress, .. } => {
+                let bar = bars.current_bar(file);
+                let detail = match (progress.samples, progress.total_samples, progress.speed) {
+                    (Some(samples), Some(total), _) => format!("{}/{} samples", samples, total),
+                    (_, _, Some(speed)) => format!("Speed: {:.2}x", speed),
+                    _ => String::new(),
+                };
+                match progress.percent {
+                    Some(percent) => {
+                        bar.set_position(percent as u64);
+                        bar.set_message(detail);
+                    }
+                    None => bar.set_message(format!("{:.1}m {}", progress.time_seconds / 60.0, detail)),
+                }
+            }
+            ProgressEvent::FileCreated { .. } => bars.finish_current(),
+            ProgressEvent::StepFinished { .. } => bars.finish(),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_progress_from_ffmpeg() {
+        let progress = FileProgress::from_ffmpeg(30.0, Some(2.0), Some(120.0));
+        assert_eq!(progress.percent, Some(25.0));
+        assert_eq!(progress.eta_seconds, Some(45.0));
+
+        // No length yet, and FFmpeg reports a speed of 0 before it has one
+        let progress = FileProgress::from_ffmpeg(30.0, Some(0.0), None);
+        assert_eq!(progress, FileProgress { time_seconds: 30.0, ..Default::default() });
+
+        assert_eq!(FileProgress::from_ffmpeg(121.0, None, Some(120.0)).percent, Some(100.0));
+    }
+
+    #[test]
+    fn test_file_progress_from_samples() {
+        let progress = FileProgress::from_samples(22050, 88200, 44100);
+        assert_eq!(progress.time_seconds, 0.5);
+        assert_eq!(progress.percent, Some(25.0));
+        assert_eq!(progress.samples, Some(22050));
+        assert_eq!(progress.total_samples, Some(88200));
+
+        assert_eq!(FileProgress::from_samples(0, 0, 44100).percent, Some(100.0));
+    }
+
+    #[test]
+    fn test_serialize_events() {
+        let progress = FileProgress::from_ffmpeg(30.0, Some(2.0), Some(120.0));
+        let event = ProgressEvent::FileProgress {
+            step: "FFmpeg",
+            file: Path::new("full_audio.wav"),
+            progress: &progress,
+        };
+        assert_eq!(
+            serde_json::to_string(&event).unwrap(),
+            r#"{"event":"file_progress","step":"FFmpeg","file":"full_audio.wav","time_seconds":30.0,"percent":25.0,"speed":2.0,"eta_seconds":45.0}"#
+        );
+
+        let event = ProgressEvent::StepFinished {
+            step: "Split",
+            index: 2,
+            status: StepStatus::Failed,
+            duration_seconds: 1.5,
+        };
+        assert_eq!(
+            serde_json::to_string(&event).unwrap(),
+            r#"{"event":"step_finished","step":"Split","index":2,"status":"failed","duration_seconds":1.5}"#
+        );
+    }
+}
@@ -0,0 +1,279 @@
+use crate::ffmpeg::command_line;
+use crate::progress::{FileProgress, ProgressEvent, ProgressReporter};
+use anyhow::Result;
+use ffmpeg_sidecar::command::FfmpegCommand;
+use lofty::config::ParseOptions;
+use lofty::file::AudioFile;
+use lofty::probe::Probe;
+use serde::Serialize;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tracing::warn;
+
+/// What happened during a pipeline run, step by step
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RunReport {
+    pub success: bool,
+    pub duration_seconds: f64,
+    /// The error the run stopped with
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub steps: Vec<StepReport>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StepReport {
+    pub name: String,
+    pub status: StepStatus,
+    pub duration_seconds: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Output files written by the step
+    pub files_created: Vec<CreatedFile>,
+    pub warnings: Vec<String>,
+    /// FFmpeg invocations as shell command lines
+    pub commands: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cleanup: Option<CleanupSummary>,
+    /// Where warnings, created files and file progress are reported as they happen
+    #[serde(skip)]
+    progress: Option<Arc<dyn ProgressReporter>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CreatedFile {
+    /// Relative to the working directory when inside it
+    pub path: PathBuf,
+    pub size: u64,
+    /// Length of audio files
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration_seconds: Option<f64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StepStatus {
+    Succeeded,
+    Failed,
+    /// Not run because of its `when` condition
+    Skipped,
+}
+
+/// Counts of a cleanup step
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct CleanupSummary {
+    /// Paths deleted or moved to the trash
+    pub removed: usize,
+    /// Paths left alone because the run did not write them
+    pub kept: usize,
+    pub failed: usize,
+    /// Why each failed path could not be removed
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub failures: Vec<String>,
+}
+
+impl StepReport {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            status: StepStatus::Succeeded,
+            duration_seconds: 0.0,
+            error: None,
+            files_created: Vec::new(),
+            warnings: Vec::new(),
+            commands: Vec::new(),
+            cleanup: None,
+            progress: None,
+        }
+    }
+
+    pub fn with_progress(mut self, progress: Arc<dyn ProgressReporter>) -> Self {
+        self.progress = Some(progress);
+        self
+    }
+
+    fn emit(&self, event: ProgressEvent) {
+        if let Some(progress) = &self.progress {
+            progress.report(&event);
+        }
+    }
+
+    /// Log a warning and keep it for the report
+    pub fn warn(&mut self, message: impl Into<String>) {
+        let message = message.into();
+        warn!("{}", message);
+        self.emit(ProgressEvent::Warning { step: &self.name, message: &message });
+        self.warnings.push(message);
+    }
+
+    /// Record an FFmpeg command before it runs
+    pub fn add_command(&mut self, command: &FfmpegCommand) {
+        self.commands.push(command_line(command));
+    }
+
+    /// Record an output file; its size and duration are read once the step finishes
+    pub fn add_output(&mut self, path: &Path) {
+        self.emit(ProgressEvent::FileCreated { step: &self.name, file: path });
+        self.files_created.push(CreatedFile {
+            path: path.to_path_buf(),
+            size: 0,
+            duration_seconds: None,
+        });
+    }
+
+    /// Report that the step moves on to the `index`th of its `total` files, counting from 1
+    pub fn start_file(&self, path: &Path, index: usize, total: usize) {
+        self.emit(ProgressEvent::FileStarted { step: &self.name, file: path, index, total });
+    }
+
+    /// Report how far the step is with writing `path`
+    pub fn file_progress(&self, path: &Path, progress: &FileProgress) {
+        self.emit(ProgressEvent::FileProgress { step: &self.name, file: path, progress });
+    }
+
+    /// Fill in sizes and audio durations, and make paths relative to the working directory
+    pub fn describe_outputs(&mut self, working_dir: &Path) {
+        for file in &mut self.files_created {
+            if let Ok(metadata) = std::fs::metadata(&file.path) {
+                file.size = metadata.len();
+            }
+            file.duration_seconds = audio_duration(&file.path).ok();
+            if let Ok(relative) = file.path.strip_prefix(working_dir) {
+                file.path = relative.to_path_buf();
+            }
+        }
+    }
+}
+
+/// Length of an audio file from its headers, failing for anything lofty cannot read
+pub fn audio_duration(path: &Path) -> Result<f64> {
+    let options = ParseOptions::new().read_tags(false).read_cover_art(false);
+    let file = Probe::open(path)?.options(options).read()?;
+    Ok(file.properties().duration().as_secs_f64())
+}
+
+impl RunReport {
+    /// One line per step, e.g. `2. Cleanup: succeeded in 0.1s (3 removed, 0 kept, 1 failed)`
+    pub fn summary(&self) -> String {
+        let mut out = String::new();
+
+        for (i, step) in self.steps.iter().enumerate() {
+            let status = match step.status {
+                StepStatus::Succeeded => "succeeded",
+                StepStatus::Failed => "failed",
+                StepStatus::Skipped => "skipped",
+            };
+            let _ = write!(out, "{}. {}: {}", i + 1, step.name, status);
+            if step.status != StepStatus::Skipped {
+                let _ = write!(out, " in {:.1}s", step.duration_seconds);
+            }
+            if !step.files_created.is_empty() {
+                let _ = write!(out, ", {} files", step.files_created.len());
+            }
+            if !step.warnings.is_empty() {
+                let _ = write!(out, ", {} warnings", step.warnings.len());
+            }
+            if let Some(cleanup) = &step.cleanup {
+                let _ = write!(out, " ({} removed, {} kept, {} failed)", cleanup.removed, cleanup.kept, cleanup.failed);
+            }
+            out.push('\n');
+        }
+
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summary() {
+        let mut tag = StepReport::new("Tag");
+        tag.duration_seconds = 2.5;
+        tag.add_output(Path::new("cover.jpg"));
+        tag.warnings.push("No files found matching pattern: 03*".to_string());
+        let mut cleanup = StepReport::new("Cleanup");
+        cleanup.status = StepStatus::Failed;
+        cleanup.cleanup = Some(CleanupSummary {
+            removed: 3,
+            kept: 1,
+            failed: 1,
+            failures: vec!["split: Permission denied".to_string()],
+        });
+        let mut skipped = StepReport::new("Cleanup");
+        skipped.status = StepStatus::Skipped;
+
+        let report = RunReport {
+            steps: vec![tag, cleanup, skipped],
+            ..Default::default()
+        };
+
+        assert_eq!(report.summary(), "\
+1. Tag: succeeded in 2.5s, 1 files, 1 warnings
+2. Cleanup: failed in 0.0s (3 removed, 1 kept, 1 failed)
+3. Cleanup: skipped
+");
+    }
+
+    #[test]
+    fn test_describe_outputs() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let wav = dir.path().join("splits/01.wav");
+        std::fs::create_dir(dir.path().join("splits")).unwrap();
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: 8000,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut writer = hound::WavWriter::create(&wav, spec).unwrap();
+        for _ in 0..4000 {
+            writer.write_sample(0i16).unwrap();
+        }
+        writer.finalize().unwrap();
+        std::fs::write(dir.path().join("01.lrc"), "[00:00.00]Hi").unwrap();
+
+        let mut step = StepReport::new("Split");
+        step.add_output(&wav);
+        step.add_output(&dir.path().join("01.lrc"));
+        step.describe_outputs(dir.path());
+
+        assert_eq!(step.files_created[0].path, PathBuf::from("splits/01.wav"));
+        assert_eq!(step.files_created[0].size, 8044);
+        assert_eq!(step.files_created[0].duration_seconds, Some(0.5));
+        assert_eq!(step.files_created[1].path, PathBuf::from("01.lrc"));
+        assert_eq!(step.files_created[1].size, 12);
+        assert_eq!(step.files_created[1].duration_seconds, None);
+    }
+
+    #[test]
+    fn test_serialize() {
+        let mut step = StepReport::new("Cleanup");
+        step.cleanup = Some(CleanupSummary { removed: 2, ..Default::default() });
+        step.commands.push("ffmpeg -i in.mkv out.wav".to_string());
+
+        let report = RunReport {
+            success: true,
+            duration_seconds: 1.5,
+            error: None,
+            steps: vec![step],
+        };
+        let json = serde_json::to_value(report).unwrap();
+
+        assert_eq!(json, serde_json::json!({
+            "success": true,
+            "duration_seconds": 1.5,
+            "steps": [{
+                "name": "Cleanup",
+                "status": "succeeded",
+                "duration_seconds": 0.0,
+                "files_created": [],
+                "warnings": [],
+                "commands": ["ffmpeg -i in.mkv out.wav"],
+                "cleanup": {"removed": 2, "kept": 0, "failed": 0}
+            }]
+        }));
+    }
+}
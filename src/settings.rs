@@ -30,6 +30,14 @@ pub struct Settings {
     )]
     #[serde(default = "default_file_scan_pattern")]
     pub file_scan_pattern: String,
+
+    /// Write the full output of every FFmpeg command to the logs directory
+    #[arg(
+        long = "ffmpeg-logs",
+        env = "FFMPEG_LOGS",
+        help = "Write the full output of every FFmpeg command to logs/ in the working directory"
+    )]
+    pub ffmpeg_logs: bool,
 }
 
 impl Default for Settings {
@@ -37,6 +45,7 @@ impl Default for Settings {
         Self {
             duration_tolerance: DEFAULT_DURATION_TOLERANCE,
             file_scan_pattern: DEFAULT_FILE_SCAN_PATTERN.to_string(),
+            ffmpeg_logs: false,
         }
     }
 }
@@ -71,6 +80,9 @@ impl Settings {
             }
         }
         // If CLI/env provided a non-default value, keep it (it takes priority)
+
+        // Logs are off by default, so either source can turn them on
+        self.ffmpeg_logs |= yaml_settings.ffmpeg_logs;
     }
 }
 
@@ -94,11 +106,13 @@ mod tests {
         let mut cli_settings = Settings {
             duration_tolerance: DEFAULT_DURATION_TOLERANCE, // CLI used default
             file_scan_pattern: DEFAULT_FILE_SCAN_PATTERN.to_string(),
+            ffmpeg_logs: false,
         };
         
         let yaml_settings = Settings {
             duration_tolerance: 6.0, // YAML provided different value
             file_scan_pattern: "*.mp4".to_string(),
+            ffmpeg_logs: false,
         };
         
         cli_settings.merge_with_yaml(&yaml_settings);
@@ -111,11 +125,13 @@ mod tests {
         let mut cli_settings = Settings {
             duration_tolerance: 4.0, // CLI provided custom value
             file_scan_pattern: "*.avi".to_string(), // CLI provided custom value
+            ffmpeg_logs: false,
         };
         
         let yaml_settings = Settings {
             duration_tolerance: 6.0, // YAML provided different value
             file_scan_pattern: "*.mp4".to_string(), // YAML provided different value
+            ffmpeg_logs: false,
         };
         
         cli_settings.merge_with_yaml(&yaml_settings);
@@ -128,15 +144,28 @@ mod tests {
         let mut cli_settings = Settings {
             duration_tolerance: DEFAULT_DURATION_TOLERANCE, // CLI used default
             file_scan_pattern: DEFAULT_FILE_SCAN_PATTERN.to_string(), // CLI used default
+            ffmpeg_logs: false,
         };
         
         let yaml_settings = Settings {
             duration_tolerance: DEFAULT_DURATION_TOLERANCE, // YAML also has default
             file_scan_pattern: DEFAULT_FILE_SCAN_PATTERN.to_string(), // YAML also has default
+            ffmpeg_logs: false,
         };
         
         cli_settings.merge_with_yaml(&yaml_settings);
         assert_eq!(cli_settings.duration_tolerance, DEFAULT_DURATION_TOLERANCE); // Default value kept
         assert_eq!(cli_settings.file_scan_pattern, DEFAULT_FILE_SCAN_PATTERN); // Default value kept
     }
+
+    #[test]
+    fn test_merge_with_yaml_ffmpeg_logs() {
+        let mut cli_settings = Settings::default();
+        cli_settings.merge_with_yaml(&Settings { ffmpeg_logs: true, ..Settings::default() });
+        assert!(cli_settings.ffmpeg_logs);
+
+        let mut cli_settings = Settings { ffmpeg_logs: true, ..Settings::default() };
+        cli_settings.merge_with_yaml(&Settings::default());
+        assert!(cli_settings.ffmpeg_logs);
+    }
 }
\ No newline at end of file
@@ -0,0 +1,190 @@
+use crate::config::TagFile;
+use anyhow::Result;
+use ffmpeg_sidecar::ffprobe::ffprobe_path;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::Path;
+use std::process::Command;
+
+/// Tags of a source container and of each of its chapters
+#[derive(Debug, Clone, Default)]
+pub struct SourceMetadata {
+    /// Album-level values from the container tags
+    pub global: TagFile,
+    /// Track-level values from each chapter, in chapter order
+    pub chapters: Vec<TagFile>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ProbeOutput {
+    #[serde(default)]
+    format: ProbeTags,
+    #[serde(default)]
+    chapters: Vec<ProbeTags>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ProbeTags {
+    #[serde(default)]
+    tags: HashMap<String, String>,
+}
+
+impl SourceMetadata {
+    /// Read container and chapter tags with ffprobe
+    pub fn probe(path: &Path) -> Result<Self> {
+        let output = Command::new(ffprobe_path())
+            .args([
+                "-v", "error",
+                "-print_format", "json",
+                "-show_format",
+                "-show_chapters",
+            ])
+            .arg(path)
+            .output()?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            anyhow::bail!("ffprobe failed for file '{}': {}", path.display(), stderr);
+        }
+
+        Self::from_ffprobe_json(&String::from_utf8_lossy(&output.stdout))
+            .map_err(|e| anyhow::anyhow!("Failed to parse ffprobe output for '{}': {}", path.display(), e))
+    }
+
+    /// Map ffprobe JSON output (`-show_format -show_chapters`) onto tag values
+    ///
+    /// The container title becomes the album unless an album tag is present, and
+    /// chapter titles become track titles. Tag names are matched case-insensitively
+    /// since Matroska uses upper case names and MP4 lower case ones.
+    pub fn from_ffprobe_json(json: &str) -> Result<Self> {
+        let output: ProbeOutput = serde_json::from_str(json)?;
+        let global_tags = lowercase_keys(output.format.tags);
+
+        let global = TagFile {
+            album: first(&global_tags, &["album", "title"]),
+            artist: first(&global_tags, &["artist", "album_artist"]),
+            album_artist: first(&global_tags, &["album_artist"]),
+            genre: first(&global_tags, &["genre"]),
+            year: first(&global_tags, &["date", "date_released", "year"])
+                .and_then(|date| parse_year(&date)),
+            comment: first(&global_tags, &["comment", "description"]),
+            ..Default::default()
+        };
+
+        let chapters = output.chapters.into_iter()
+            .map(|chapter| {
+                let tags = lowercase_keys(chapter.tags);
+                TagFile {
+                    title: first(&tags, &["title"]),
+                    artist: first(&tags, &["artist"]),
+                    ..Default::default()
+                }
+            })
+            .collect();
+
+        Ok(Self { global, chapters })
+    }
+
+    /// Values for a track: chapter `track` (1-based) on top of the container values
+    pub fn for_track(&self, track: Option<u32>) -> TagFile {
+        let chapter = track
+            .and_then(|track| track.checked_sub(1))
+            .and_then(|index| self.chapters.get(index as usize));
+
+        match chapter {
+            Some(chapter) => chapter.merged_with(&self.global),
+            None => self.global.clone(),
+        }
+    }
+}
+
+fn lowercase_keys(tags: HashMap<String, String>) -> HashMap<String, String> {
+    tags.into_iter()
+        .map(|(key, value)| (key.to_ascii_lowercase(), value))
+        .collect()
+}
+
+/// The first non-empty value among the given tag names
+fn first(tags: &HashMap<String, String>, names: &[&str]) -> Option<String> {
+    names.iter()
+        .filter_map(|name| tags.get(*name))
+        .map(|value| value.trim())
+        .find(|value| !value.is_empty())
+        .map(|value| value.to_string())
+}
+
+/// Take the year from dates such as `2024`, `2024-05-01` or `2024-05-01T20:00:00Z`
+fn parse_year(date: &str) -> Option<u32> {
+    let year = date.get(..4)?;
+    if !year.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    year.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MKV_PROBE: &str = r#"{
+        "chapters": [
+            {"id": 1, "start_time": "0.000000", "end_time": "210.500000", "tags": {"title": "Intro"}},
+            {"id": 2, "start_time": "210.500000", "end_time": "435.750000", "tags": {"title": "Song", "ARTIST": "Guest"}},
+            {"id": 3, "start_time": "435.750000", "end_time": "500.000000"}
+        ],
+        "format": {
+            "filename": "concert.mkv",
+            "tags": {"TITLE": "Live at the Hall", "ARTIST": "Band", "DATE_RELEASED": "2024-05-01", "ENCODER": "libebml"}
+        }
+    }"#;
+
+    #[test]
+    fn test_from_ffprobe_json() {
+        let metadata = SourceMetadata::from_ffprobe_json(MKV_PROBE).unwrap();
+
+        assert_eq!(metadata.global.album, Some("Live at the Hall".to_string()));
+        assert_eq!(metadata.global.artist, Some("Band".to_string()));
+        assert_eq!(metadata.global.year, Some(2024));
+        assert_eq!(metadata.global.title, None);
+        assert_eq!(metadata.chapters.len(), 3);
+        assert_eq!(metadata.chapters[0].title, Some("Intro".to_string()));
+        assert_eq!(metadata.chapters[2].title, None);
+    }
+
+    #[test]
+    fn test_for_track() {
+        let metadata = SourceMetadata::from_ffprobe_json(MKV_PROBE).unwrap();
+
+        let track = metadata.for_track(Some(2));
+        assert_eq!(track.title, Some("Song".to_string()));
+        assert_eq!(track.artist, Some("Guest".to_string()));
+        assert_eq!(track.album, Some("Live at the Hall".to_string()));
+
+        let track = metadata.for_track(Some(1));
+        assert_eq!(track.artist, Some("Band".to_string()));
+
+        // Tracks without a chapter only get the container values
+        assert_eq!(metadata.for_track(Some(9)).title, None);
+        assert_eq!(metadata.for_track(None).album, Some("Live at the Hall".to_string()));
+    }
+
+    #[test]
+    fn test_from_ffprobe_json_mp4() {
+        let json = r#"{"format": {"tags": {"title": "Video", "album": "Album", "date": "2023", "comment": "Recorded live"}}}"#;
+
+        let metadata = SourceMetadata::from_ffprobe_json(json).unwrap();
+
+        assert_eq!(metadata.global.album, Some("Album".to_string()));
+        assert_eq!(metadata.global.year, Some(2023));
+        assert_eq!(metadata.global.comment, Some("Recorded live".to_string()));
+        assert!(metadata.chapters.is_empty());
+    }
+
+    #[test]
+    fn test_parse_year() {
+        assert_eq!(parse_year("2024-05-01T20:00:00.000000Z"), Some(2024));
+        assert_eq!(parse_year("1999"), Some(1999));
+        assert_eq!(parse_year("May 2024"), None);
+        assert_eq!(parse_year("99"), None);
+    }
+}
@@ -0,0 +1,327 @@
+use crate::file_suggester::format_duration;
+use crate::pipeline::tag_step::read_tagged_file;
+use anyhow::Result;
+use lofty::file::{AudioFile, FileType, TaggedFile, TaggedFileExt};
+use lofty::config::ParseOptions;
+use lofty::mp4::{Mp4Codec, Mp4File};
+use lofty::picture::Picture;
+use lofty::tag::{ItemKey, ItemValue, Tag};
+use serde::Serialize;
+use std::fmt::Write;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+
+/// Everything lofty reads from one audio file
+#[derive(Debug, Clone, Serialize)]
+pub struct FileDump {
+    pub path: PathBuf,
+    pub properties: AudioProperties,
+    pub tags: Vec<TagDump>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AudioProperties {
+    pub codec: String,
+    pub sample_rate: Option<u32>,
+    pub bit_depth: Option<u8>,
+    pub channels: Option<u8>,
+    /// Audio bitrate in kbps
+    pub bitrate: Option<u32>,
+    pub duration_seconds: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TagDump {
+    pub tag_type: String,
+    pub items: Vec<ItemDump>,
+    pub pictures: Vec<PictureDump>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ItemDump {
+    /// Format-independent key name, e.g. `TrackTitle`
+    pub key: String,
+    /// Key as stored in this tag type, e.g. `TIT2` or `TITLE`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub native_key: Option<String>,
+    pub value: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PictureDump {
+    pub picture_type: String,
+    pub mime_type: Option<String>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub size: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+/// Expand file paths and glob patterns, keeping the order they were given in
+pub fn resolve_paths(patterns: &[String]) -> Result<Vec<PathBuf>> {
+    let mut paths = Vec::new();
+
+    for pattern in patterns {
+        if !pattern.contains(['*', '?', '[']) {
+            paths.push(PathBuf::from(pattern));
+            continue;
+        }
+
+        let mut matches: Vec<PathBuf> = glob::glob(pattern)?
+            .collect::<Result<Vec<_>, _>>()?
+            .into_iter()
+            .filter(|p| p.is_file())
+            .collect();
+        if matches.is_empty() {
+            anyhow::bail!("No files matching pattern '{}'", pattern);
+        }
+        matches.sort();
+        paths.extend(matches);
+    }
+
+    Ok(paths)
+}
+
+/// Read the tags, pictures and audio properties of a file
+pub fn dump_file(path: &Path) -> Result<FileDump> {
+    let tagged_file = read_tagged_file(path)?;
+
+    Ok(FileDump {
+        path: path.to_path_buf(),
+        properties: audio_properties(path, &tagged_file),
+        tags: tagged_file.tags().iter().map(dump_tag).collect(),
+    })
+}
+
+fn audio_properties(path: &Path, tagged_file: &TaggedFile) -> AudioProperties {
+    let properties = tagged_file.properties();
+
+    AudioProperties {
+        codec: codec_name(path, tagged_file.file_type()),
+        sample_rate: properties.sample_rate(),
+        bit_depth: properties.bit_depth(),
+        channels: properties.channels(),
+        bitrate: properties.audio_bitrate(),
+        duration_seconds: properties.duration().as_secs_f64(),
+    }
+}
+
+fn codec_name(path: &Path, file_type: FileType) -> String {
+    let name = match file_type {
+        FileType::Aac => "AAC",
+        FileType::Aiff => "AIFF",
+        FileType::Ape => "APE",
+        FileType::Flac => "FLAC",
+        FileType::Mpeg => "MP3",
+        FileType::Mpc => "Musepack",
+        FileType::Opus => "Opus",
+        FileType::Vorbis => "Vorbis",
+        FileType::Speex => "Speex",
+        FileType::Wav => "WAV",
+        FileType::WavPack => "WavPack",
+        // The generic properties do not tell AAC and ALAC apart
+        FileType::Mp4 => return mp4_codec(path).unwrap_or_else(|| "MP4".to_string()),
+        FileType::Custom(name) => name,
+        _ => "Unknown",
+    };
+    name.to_string()
+}
+
+fn mp4_codec(path: &Path) -> Option<String> {
+    let mut file = std::fs::File::open(path).ok()?;
+    let mp4 = Mp4File::read_from(&mut file, ParseOptions::new().read_tags(false)).ok()?;
+
+    let name = match mp4.properties().codec() {
+        Mp4Codec::AAC => "AAC",
+        Mp4Codec::ALAC => "ALAC",
+        Mp4Codec::MP3 => "MP3",
+        Mp4Codec::FLAC => "FLAC",
+        _ => return None,
+    };
+    Some(name.to_string())
+}
+
+fn dump_tag(tag: &Tag) -> TagDump {
+    let items = tag.items()
+        .map(|item| {
+            let (key, native_key) = match item.key() {
+                ItemKey::Unknown(key) => (key.clone(), None),
+                key => (format!("{:?}", key), key.map_key(tag.tag_type(), true).map(|k| k.to_string())),
+            };
+            let value = match item.value() {
+                ItemValue::Text(text) | ItemValue::Locator(text) => text.clone(),
+                ItemValue::Binary(data) => format!("<{} bytes>", data.len()),
+            };
+            ItemDump { key, native_key, value }
+        })
+        .collect();
+
+    TagDump {
+        tag_type: format!("{:?}", tag.tag_type()),
+        items,
+        pictures: tag.pictures().iter().map(dump_picture).collect(),
+    }
+}
+
+fn dump_picture(picture: &Picture) -> PictureDump {
+    // Only JPEG, PNG, GIF, BMP and TIFF can be decoded; other images report no dimensions
+    let dimensions = image::ImageReader::new(Cursor::new(picture.data()))
+        .with_guessed_format()
+        .ok()
+        .and_then(|reader| reader.into_dimensions().ok());
+
+    PictureDump {
+        picture_type: format!("{:?}", picture.pic_type()),
+        mime_type: picture.mime_type().map(|mime| mime.as_str().to_string()),
+        width: dimensions.map(|(width, _)| width),
+        height: dimensions.map(|(_, height)| height),
+        size: picture.data().len(),
+        description: picture.description().map(|d| d.to_string()),
+    }
+}
+
+/// Render dumps as indented plain text, one block per file
+pub fn format_table(dumps: &[FileDump]) -> String {
+    let mut out = String::new();
+
+    for (i, dump) in dumps.iter().enumerate() {
+        if i > 0 {
+            out.push('\n');
+        }
+        let props = &dump.properties;
+        let mut audio = vec![props.codec.clone()];
+        audio.extend(props.sample_rate.map(|rate| format!("{} Hz", rate)));
+        audio.extend(props.bit_depth.map(|depth| format!("{} bit", depth)));
+        audio.extend(props.channels.map(|channels| format!("{} ch", channels)));
+        audio.extend(props.bitrate.map(|bitrate| format!("{} kbps", bitrate)));
+        audio.push(format_duration(props.duration_seconds));
+
+        let _ = writeln!(out, "{}", dump.path.display());
+        let _ = writeln!(out, "  Audio: {}", audio.join(", "));
+
+        if dump.tags.is_empty() {
+            let _ = writeln!(out, "  No tags");
+        }
+        for tag in &dump.tags {
+            let _ = writeln!(out, "  {}:", tag.tag_type);
+            let width = tag.items.iter().map(|item| item_label(item).len()).max().unwrap_or(0);
+            for item in &tag.items {
+                let value = item.value.replace('\n', "\\n");
+                let _ = writeln!(out, "    {:width$}  {}", item_label(item), value, width = width);
+            }
+            for picture in &tag.pictures {
+                let dimensions = match (picture.width, picture.height) {
+                    (Some(width), Some(height)) => format!("{}x{}", width, height),
+                    _ => "?".to_string(),
+                };
+                let _ = write!(out, "    Picture {}: {}, {}, {} bytes",
+                               picture.picture_type, picture.mime_type.as_deref().unwrap_or("unknown type"),
+                               dimensions, picture.size);
+                match &picture.description {
+                    Some(description) if !description.is_empty() => {
+                        let _ = writeln!(out, " \"{}\"", description);
+                    }
+                    _ => out.push('\n'),
+                }
+            }
+        }
+    }
+
+    out
+}
+
+fn item_label(item: &ItemDump) -> String {
+    match &item.native_key {
+        Some(native) if *native != item.key => format!("{} ({})", item.key, native),
+        _ => item.key.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lofty::picture::{MimeType, PictureType};
+    use lofty::prelude::*;
+    use lofty::tag::{TagItem, TagType};
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let mut data = Vec::new();
+        image::DynamicImage::new_rgb8(width, height)
+            .write_to(&mut Cursor::new(&mut data), image::ImageFormat::Png)
+            .unwrap();
+        data
+    }
+
+    #[test]
+    fn test_dump_tag() {
+        let mut tag = Tag::new(TagType::Id3v2);
+        tag.set_title("Intro".to_string());
+        tag.insert_unchecked(TagItem::new(ItemKey::Unknown("SOURCE".to_string()), ItemValue::Text("live".to_string())));
+        tag.push_picture(Picture::new_unchecked(PictureType::CoverFront, Some(MimeType::Png), None, png(30, 20)));
+
+        let dump = dump_tag(&tag);
+
+        assert_eq!(dump.tag_type, "Id3v2");
+        assert_eq!(dump.items[0].key, "TrackTitle");
+        assert_eq!(dump.items[0].native_key.as_deref(), Some("TIT2"));
+        assert_eq!(dump.items[1].key, "SOURCE");
+        assert_eq!(dump.pictures[0].picture_type, "CoverFront");
+        assert_eq!(dump.pictures[0].mime_type.as_deref(), Some("image/png"));
+        assert_eq!((dump.pictures[0].width, dump.pictures[0].height), (Some(30), Some(20)));
+    }
+
+    #[test]
+    fn test_format_table() {
+        let dump = FileDump {
+            path: PathBuf::from("01.flac"),
+            properties: AudioProperties {
+                codec: "FLAC".to_string(),
+                sample_rate: Some(44100),
+                bit_depth: Some(16),
+                channels: Some(2),
+                bitrate: Some(900),
+                duration_seconds: 90.5,
+            },
+            tags: vec![TagDump {
+                tag_type: "VorbisComments".to_string(),
+                items: vec![ItemDump {
+                    key: "TrackTitle".to_string(),
+                    native_key: Some("TITLE".to_string()),
+                    value: "Intro".to_string(),
+                }],
+                pictures: vec![PictureDump {
+                    picture_type: "CoverFront".to_string(),
+                    mime_type: Some("image/jpeg".to_string()),
+                    width: Some(500),
+                    height: Some(500),
+                    size: 1234,
+                    description: None,
+                }],
+            }],
+        };
+
+        assert_eq!(format_table(&[dump]), "\
+01.flac
+  Audio: FLAC, 44100 Hz, 16 bit, 2 ch, 900 kbps, 0:01:30.500
+  VorbisComments:
+    TrackTitle (TITLE)  Intro
+    Picture CoverFront: image/jpeg, 500x500, 1234 bytes
+");
+    }
+
+    #[test]
+    fn test_resolve_paths() {
+        let dir = tempfile::TempDir::new().unwrap();
+        std::fs::write(dir.path().join("b.flac"), "").unwrap();
+        std::fs::write(dir.path().join("a.flac"), "").unwrap();
+        let pattern = dir.path().join("*.flac").to_string_lossy().to_string();
+
+        let paths = resolve_paths(&[pattern, "missing.mp3".to_string()]).unwrap();
+
+        assert_eq!(paths, vec![dir.path().join("a.flac"), dir.path().join("b.flac"), PathBuf::from("missing.mp3")]);
+
+        let pattern = dir.path().join("*.mp3").to_string_lossy().to_string();
+        assert!(resolve_paths(&[pattern]).is_err());
+    }
+}
@@ -0,0 +1,226 @@
+use crate::config::TagFile;
+use anyhow::Result;
+use serde_json::{Map, Value};
+use std::path::Path;
+
+/// Tag fields accepted as CSV/TSV columns or JSON keys
+const TEXT_FIELDS: &[&str] = &[
+    "file", "title", "artist", "album", "album_artist", "album_art",
+    "genre", "comment", "lyrics", "date", "isrc",
+];
+const NUMBER_FIELDS: &[&str] = &["track", "track_total", "disk", "disk_total", "year"];
+
+/// Prefix of columns that become `custom` fields, e.g. `custom:SOURCE_URL`
+const CUSTOM_PREFIX: &str = "custom:";
+
+/// Load tag entries from a `.csv`, `.tsv` or `.json` track list
+///
+/// CSV/TSV files need a header row naming the tag field of each column; columns
+/// named `custom:<key>` set custom fields and empty cells leave a field unset.
+/// JSON files contain an array of objects with the same keys as `files` entries.
+pub fn load_tag_list(path: &Path) -> Result<Vec<TagFile>> {
+    let extension = path.extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_ascii_lowercase());
+
+    let entries = match extension.as_deref() {
+        Some("csv") => read_delimited(path, b','),
+        Some("tsv") | Some("tab") => read_delimited(path, b'\t'),
+        Some("json") => read_json(path),
+        _ => anyhow::bail!("Unsupported tag list format: {}. Expected .csv, .tsv or .json", path.display()),
+    };
+
+    entries.map_err(|e| anyhow::anyhow!("Invalid tag list '{}': {}", path.display(), e))
+}
+
+/// How an entry is referred to in messages: the CSV/TSV line or the JSON array position
+pub fn entry_label(path: &Path, index: usize) -> String {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some(ext) if ext.eq_ignore_ascii_case("json") => format!("Entry {}", index + 1),
+        // Line 1 is the header
+        _ => format!("Row {}", index + 2),
+    }
+}
+
+fn read_delimited(path: &Path, delimiter: u8) -> Result<Vec<TagFile>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .trim(csv::Trim::All)
+        .from_path(path)?;
+
+    let headers: Vec<String> = reader.headers()?
+        .iter()
+        .map(|header| header.to_string())
+        .collect();
+
+    for header in &headers {
+        if header.strip_prefix(CUSTOM_PREFIX).is_none() && !is_known_field(header) {
+            anyhow::bail!(
+                "Unknown column '{}'. Expected one of: {}, {}, or {}<key>",
+                header, TEXT_FIELDS.join(", "), NUMBER_FIELDS.join(", "), CUSTOM_PREFIX
+            );
+        }
+    }
+    if !headers.iter().any(|header| header == "file") {
+        anyhow::bail!("Missing 'file' column");
+    }
+
+    let mut entries = Vec::new();
+    for (i, record) in reader.records().enumerate() {
+        let record = record?;
+        let row = entry_label(path, i);
+
+        let mut object = Map::new();
+        let mut custom = Map::new();
+        for (header, cell) in headers.iter().zip(record.iter()) {
+            if cell.is_empty() {
+                continue;
+            }
+
+            if let Some(key) = header.strip_prefix(CUSTOM_PREFIX) {
+                custom.insert(key.to_string(), Value::String(cell.to_string()));
+            } else if NUMBER_FIELDS.contains(&header.as_str()) {
+                let number: u32 = cell.parse()
+                    .map_err(|_| anyhow::anyhow!("{}: invalid {} '{}'", row, header, cell))?;
+                object.insert(header.clone(), Value::from(number));
+            } else {
+                object.insert(header.clone(), Value::String(cell.to_string()));
+            }
+        }
+        if !custom.is_empty() {
+            object.insert("custom".to_string(), Value::Object(custom));
+        }
+
+        entries.push(to_tag_file(object, &row)?);
+    }
+
+    Ok(entries)
+}
+
+fn read_json(path: &Path) -> Result<Vec<TagFile>> {
+    let content = std::fs::read_to_string(path)?;
+    let value: Value = serde_json::from_str(&content)?;
+
+    let Value::Array(items) = value else {
+        anyhow::bail!("Expected a JSON array of tag entries");
+    };
+
+    items.into_iter()
+        .enumerate()
+        .map(|(i, item)| {
+            let context = entry_label(path, i);
+            let Value::Object(object) = item else {
+                anyhow::bail!("{}: expected an object", context);
+            };
+            for key in object.keys() {
+                if !is_known_field(key) && key != "custom" && key != "images" {
+                    anyhow::bail!("{}: unknown field '{}'", context, key);
+                }
+            }
+            to_tag_file(object, &context)
+        })
+        .collect()
+}
+
+fn is_known_field(name: &str) -> bool {
+    TEXT_FIELDS.contains(&name) || NUMBER_FIELDS.contains(&name)
+}
+
+fn to_tag_file(object: Map<String, Value>, context: &str) -> Result<TagFile> {
+    if object.get("file").and_then(Value::as_str).is_none_or(str::is_empty) {
+        anyhow::bail!("{}: missing file", context);
+    }
+
+    serde_json::from_value(Value::Object(object))
+        .map_err(|e| anyhow::anyhow!("{}: {}", context, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn write(dir: &TempDir, name: &str, content: &str) -> std::path::PathBuf {
+        let path = dir.path().join(name);
+        std::fs::write(&path, content).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_load_tag_list_csv() {
+        let dir = TempDir::new().unwrap();
+        let path = write(&dir, "tracks.csv", "\
+file,track,title,artist,custom:SOURCE_URL
+01.*,1,\"Intro, Part 1\",Band,https://example.com/1
+02.*,2,Song,,
+");
+
+        let entries = load_tag_list(&path).unwrap();
+
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].file, "01.*");
+        assert_eq!(entries[0].track, Some(1));
+        assert_eq!(entries[0].title, Some("Intro, Part 1".to_string()));
+        assert_eq!(entries[0].custom.as_ref().unwrap()["SOURCE_URL"], "https://example.com/1");
+        assert_eq!(entries[1].artist, None);
+        assert!(entries[1].custom.is_none());
+    }
+
+    #[test]
+    fn test_load_tag_list_tsv() {
+        let dir = TempDir::new().unwrap();
+        let path = write(&dir, "tracks.tsv", "file\ttitle\tyear\n01.flac\tFirst\t2024\n");
+
+        let entries = load_tag_list(&path).unwrap();
+
+        assert_eq!(entries[0].title, Some("First".to_string()));
+        assert_eq!(entries[0].year, Some(2024));
+    }
+
+    #[test]
+    fn test_load_tag_list_csv_errors() {
+        let dir = TempDir::new().unwrap();
+
+        let path = write(&dir, "unknown.csv", "file,titel\n01.*,Intro\n");
+        let error = load_tag_list(&path).unwrap_err().to_string();
+        assert!(error.contains("Unknown column 'titel'"), "{}", error);
+
+        let path = write(&dir, "number.csv", "file,track\n01.*,1\n02.*,two\n");
+        let error = load_tag_list(&path).unwrap_err().to_string();
+        assert!(error.contains("Row 3: invalid track 'two'"), "{}", error);
+
+        let path = write(&dir, "no_file.csv", "title\nIntro\n");
+        assert!(load_tag_list(&path).unwrap_err().to_string().contains("Missing 'file' column"));
+
+        let path = write(&dir, "empty_file.csv", "file,title\n,Intro\n");
+        assert!(load_tag_list(&path).unwrap_err().to_string().contains("Row 2: missing file"));
+    }
+
+    #[test]
+    fn test_load_tag_list_json() {
+        let dir = TempDir::new().unwrap();
+        let path = write(&dir, "tracks.json", r#"[
+            {"file": "01.*", "title": "Intro", "track": 1, "images": [{"file": "back.jpg", "type": "back_cover"}]},
+            {"file": "02.*", "title": "Song", "custom": {"MOOD": "calm"}}
+        ]"#);
+
+        let entries = load_tag_list(&path).unwrap();
+
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].track, Some(1));
+        assert_eq!(entries[0].images.as_ref().unwrap()[0].file, "back.jpg");
+        assert_eq!(entries[1].custom.as_ref().unwrap()["MOOD"], "calm");
+
+        let path = write(&dir, "unknown.json", r#"[{"file": "01.*", "tittle": "Intro"}]"#);
+        let error = load_tag_list(&path).unwrap_err().to_string();
+        assert!(error.contains("Entry 1: unknown field 'tittle'"), "{}", error);
+    }
+
+    #[test]
+    fn test_load_tag_list_unsupported_extension() {
+        let dir = TempDir::new().unwrap();
+        let path = write(&dir, "tracks.xlsx", "");
+
+        assert!(load_tag_list(&path).is_err());
+    }
+}
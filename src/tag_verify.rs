@@ -0,0 +1,247 @@
+use crate::album_art::AlbumArt;
+use crate::config::{TagFile, TagImage};
+use crate::lyrics;
+use crate::pipeline::tag_step::{custom_item_key, picture_type};
+use lofty::id3::v2::Id3v2Tag;
+use lofty::picture::Picture;
+use lofty::prelude::*;
+use lofty::tag::{Tag, TagType};
+use std::fmt;
+
+/// Longest value shown in a mismatch before it is shortened
+const MAX_SHOWN_CHARS: usize = 60;
+
+/// A configured value that did not read back from the saved file
+#[derive(Debug, Clone, PartialEq)]
+pub struct TagMismatch {
+    pub field: String,
+    pub expected: String,
+    /// What the file contains instead, `None` when the field is missing
+    pub actual: Option<String>,
+}
+
+impl fmt::Display for TagMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.actual {
+            Some(actual) => write!(f, "{}: expected '{}', found '{}'",
+                                   self.field, shorten(&self.expected), shorten(actual)),
+            None => write!(f, "{}: expected '{}', but it is missing", self.field, shorten(&self.expected)),
+        }
+    }
+}
+
+fn shorten(value: &str) -> String {
+    let value = value.replace('\n', "\\n");
+    if value.chars().count() <= MAX_SHOWN_CHARS {
+        return value;
+    }
+    let shortened: String = value.chars().take(MAX_SHOWN_CHARS).collect();
+    format!("{}...", shortened)
+}
+
+/// Compare a tag read back from a file with the values the tag step wrote
+///
+/// Only configured fields are checked, so values the file already had are ignored.
+/// `pictures` are the embedded images with their processed data.
+pub fn verify_tag(tag: &Tag, expected: &TagFile, pictures: &[(TagImage, &AlbumArt)]) -> Vec<TagMismatch> {
+    let mut mismatches = Vec::new();
+    let mut check = |field: &str, expected: Option<String>, actual: Option<String>| {
+        if let Some(expected) = expected {
+            if actual.as_deref() != Some(expected.as_str()) {
+                mismatches.push(TagMismatch {
+                    field: field.to_string(),
+                    expected,
+                    actual,
+                });
+            }
+        }
+    };
+    let text = |key: ItemKey| tag.get_string(&key).map(|value| value.to_string());
+    let number = |value: Option<u32>| value.map(|n| n.to_string());
+
+    check("title", expected.title.clone(), tag.title().map(|v| v.to_string()));
+    check("artist", expected.artist.clone(), tag.artist().map(|v| v.to_string()));
+    check("album", expected.album.clone(), tag.album().map(|v| v.to_string()));
+    check("album_artist", expected.album_artist.clone(), text(ItemKey::AlbumArtist));
+    check("track", number(expected.track), number(tag.track()));
+    check("track_total", number(expected.track_total), number(tag.track_total()));
+    check("disk", number(expected.disk), number(tag.disk()));
+    check("disk_total", number(expected.disk_total), number(tag.disk_total()));
+    check("genre", expected.genre.clone(), tag.genre().map(|v| v.to_string()));
+    check("year", number(expected.year), number(tag.year()));
+    check("date", expected.date.clone(), text(ItemKey::RecordingDate));
+    check("comment", expected.comment.clone(), tag.comment().map(|v| v.to_string()));
+    check("isrc", expected.isrc.clone(), text(ItemKey::Isrc));
+
+    if let Some(ids) = &expected.musicbrainz {
+        let joined = |key: ItemKey| {
+            let values: Vec<&str> = tag.get_strings(&key).collect();
+            (!values.is_empty()).then(|| values.join("; "))
+        };
+        check("musicbrainz.release_id", ids.release_id.clone(), text(ItemKey::MusicBrainzReleaseId));
+        check("musicbrainz.release_group_id", ids.release_group_id.clone(), text(ItemKey::MusicBrainzReleaseGroupId));
+        check("musicbrainz.track_id", ids.track_id.clone(), text(ItemKey::MusicBrainzTrackId));
+        check("musicbrainz.recording_id", ids.recording_id.clone(), text(ItemKey::MusicBrainzRecordingId));
+        check("musicbrainz.release_artist_ids", ids.release_artist_ids.as_ref().map(|ids| ids.join("; ")),
+              joined(ItemKey::MusicBrainzReleaseArtistId));
+        check("musicbrainz.artist_ids", ids.artist_ids.as_ref().map(|ids| ids.join("; ")),
+              joined(ItemKey::MusicBrainzArtistId));
+    }
+
+    // lofty keeps TXXX frames with 4-character descriptions out of the generic tag
+    let id3v2 = (tag.tag_type() == TagType::Id3v2).then(|| Id3v2Tag::from(tag.clone()));
+    for (key, value) in expected.custom.iter().flatten() {
+        // Invalid keys already failed while writing
+        if let Ok(item_key) = custom_item_key(tag.tag_type(), key) {
+            let actual = match &id3v2 {
+                Some(id3v2) if key.len() == 4 => id3v2.get_user_text(key).map(|value| value.to_string()),
+                _ => text(item_key),
+            };
+            check(&format!("custom.{}", key), Some(value.clone()), actual);
+        }
+    }
+
+    // Lyrics without text are not written, so there is nothing to read back
+    let configured_lyrics = expected.lyrics.as_deref()
+        .and_then(|value| lyrics::load_lyrics(value).ok())
+        .filter(|lyrics| !lyrics.is_empty());
+    if let Some(lyrics) = configured_lyrics {
+        let written = if tag.tag_type() == TagType::Id3v2 {
+            lyrics.plain_text()
+        } else {
+            lyrics.raw.trim().to_string()
+        };
+        check("lyrics", Some(written), text(ItemKey::Lyrics));
+    }
+
+    // MP4 stores every picture as untyped cover art
+    let typed = tag.tag_type() != TagType::Mp4Ilst;
+    for (image, art) in pictures {
+        let same_type = |picture: &&Picture| !typed || picture.pic_type() == picture_type(image.image_type);
+        if tag.pictures().iter().filter(same_type).any(|picture| picture.data() == art.data.as_slice()) {
+            continue;
+        }
+
+        let found = tag.pictures().iter()
+            .filter(same_type)
+            .map(|picture| format!("{} bytes", picture.data().len()))
+            .collect::<Vec<_>>();
+        check(
+            &format!("picture ({:?})", image.image_type),
+            Some(format!("{} ({} bytes)", image.file, art.data.len())),
+            (!found.is_empty()).then(|| found.join(", ")),
+        );
+    }
+
+    mismatches
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{ImageType, MusicBrainzIds};
+    use lofty::picture::{MimeType, PictureType};
+    use lofty::tag::{ItemValue, TagItem};
+    use std::collections::BTreeMap;
+
+    fn expected() -> TagFile {
+        TagFile {
+            file: "01.mp3".to_string(),
+            title: Some("Intro".to_string()),
+            artist: Some("Band".to_string()),
+            track: Some(1),
+            track_total: Some(10),
+            year: Some(2024),
+            custom: Some(BTreeMap::from([("SOURCE".to_string(), "live".to_string())])),
+            musicbrainz: Some(MusicBrainzIds {
+                artist_ids: Some(vec!["a1".to_string(), "a2".to_string()]),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_verify_tag_matching() {
+        let mut tag = Tag::new(TagType::Id3v2);
+        tag.set_title("Intro".to_string());
+        tag.set_artist("Band".to_string());
+        tag.set_track(1);
+        tag.set_track_total(10);
+        tag.set_year(2024);
+        tag.insert_unchecked(TagItem::new(ItemKey::Unknown("SOURCE".to_string()), ItemValue::Text("live".to_string())));
+        tag.push(TagItem::new(ItemKey::MusicBrainzArtistId, ItemValue::Text("a1".to_string())));
+        tag.push(TagItem::new(ItemKey::MusicBrainzArtistId, ItemValue::Text("a2".to_string())));
+        // Values that were not configured are not compared
+        tag.set_album("Existing".to_string());
+
+        assert_eq!(verify_tag(&tag, &expected(), &[]), vec![]);
+    }
+
+    #[test]
+    fn test_verify_tag_mismatches() {
+        let mut tag = Tag::new(TagType::Id3v2);
+        tag.set_title("Intro (Live)".to_string());
+        tag.set_track(1);
+
+        let mismatches = verify_tag(&tag, &expected(), &[]);
+        let fields: Vec<&str> = mismatches.iter().map(|m| m.field.as_str()).collect();
+
+        assert_eq!(fields, vec!["title", "artist", "track_total", "year", "musicbrainz.artist_ids", "custom.SOURCE"]);
+        assert_eq!(mismatches[0].to_string(), "title: expected 'Intro', found 'Intro (Live)'");
+        assert_eq!(mismatches[1].to_string(), "artist: expected 'Band', but it is missing");
+    }
+
+    #[test]
+    fn test_verify_tag_pictures() {
+        let art = AlbumArt {
+            mime_type: MimeType::Jpeg,
+            data: vec![0xFF, 0xD8, 0xFF, 0xE0, 1, 2, 3],
+        };
+        let image = TagImage {
+            file: "back.jpg".to_string(),
+            image_type: ImageType::BackCover,
+            description: None,
+        };
+        let picture = |pic_type| Picture::new_unchecked(pic_type, Some(MimeType::Jpeg), None, art.data.clone());
+
+        let mut tag = Tag::new(TagType::VorbisComments);
+        tag.push_picture(picture(PictureType::CoverFront));
+        let mismatches = verify_tag(&tag, &TagFile::default(), &[(image.clone(), &art)]);
+        assert_eq!(mismatches.len(), 1);
+        assert_eq!(mismatches[0].to_string(), "picture (BackCover): expected 'back.jpg (7 bytes)', but it is missing");
+
+        tag.push_picture(picture(PictureType::CoverBack));
+        assert!(verify_tag(&tag, &TagFile::default(), &[(image.clone(), &art)]).is_empty());
+
+        // MP4 has no picture types
+        let mut tag = Tag::new(TagType::Mp4Ilst);
+        tag.push_picture(picture(PictureType::Other));
+        assert!(verify_tag(&tag, &TagFile::default(), &[(image, &art)]).is_empty());
+    }
+
+    #[test]
+    fn test_verify_tag_skips_empty_lyrics() {
+        let tag = Tag::new(TagType::Id3v2);
+        let lyrics = |value: &str| TagFile { lyrics: Some(value.to_string()), ..Default::default() };
+
+        assert!(verify_tag(&tag, &lyrics("[ar:Band]\n[00:00.00]"), &[]).is_empty());
+        let mismatches = verify_tag(&tag, &lyrics("[00:01.00]Hello"), &[]);
+        assert_eq!(mismatches.len(), 1);
+        assert_eq!(mismatches[0].field, "lyrics");
+    }
+
+    #[test]
+    fn test_mismatch_shortens_long_values() {
+        let mismatch = TagMismatch {
+            field: "lyrics".to_string(),
+            expected: "a\n".repeat(100),
+            actual: None,
+        };
+
+        let shown = mismatch.to_string();
+        assert!(shown.len() < 120, "{}", shown);
+        assert!(shown.contains("a\\na\\n"));
+        assert!(shown.contains("...'"));
+    }
+}
@@ -0,0 +1,317 @@
+use crate::workspace;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::path::{Component, Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Directory in the working directory that trashed paths are moved into
+pub const TRASH_DIR: &str = ".soundpipeline-trash";
+
+const MANIFEST_FILE: &str = "manifest.json";
+const FILES_DIR: &str = "files";
+
+/// What one cleanup moved into the trash, saved next to the files
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TrashManifest {
+    /// UTC time the batch was created, e.g. `2024-05-01T12:30:00Z`
+    pub created_at: String,
+    pub entries: Vec<TrashEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TrashEntry {
+    /// Where the path was, relative to the working directory
+    pub original: PathBuf,
+    pub directory: bool,
+}
+
+/// A timestamped directory in the trash that paths are moved into
+pub struct TrashBatch {
+    root: PathBuf,
+    dir: PathBuf,
+    manifest: TrashManifest,
+}
+
+/// Outcome of restoring a batch
+#[derive(Debug, Default)]
+pub struct RestoreSummary {
+    pub restored: Vec<PathBuf>,
+    /// Paths left in the trash because something exists at the original location again
+    pub skipped: Vec<PathBuf>,
+}
+
+impl TrashBatch {
+    /// Create a new batch named after the current UTC time
+    pub fn create(working_dir: &Path) -> Result<Self> {
+        let root = working_dir.canonicalize()?;
+        let (name, created_at) = timestamps(SystemTime::now());
+        let trash = root.join(TRASH_DIR);
+
+        // Two cleanups within the same second get separate batches
+        let mut dir = trash.join(&name);
+        let mut suffix = 2;
+        while dir.exists() {
+            dir = trash.join(format!("{}-{}", name, suffix));
+            suffix += 1;
+        }
+        std::fs::create_dir_all(dir.join(FILES_DIR))?;
+
+        let batch = Self {
+            root,
+            dir,
+            manifest: TrashManifest { created_at, entries: Vec::new() },
+        };
+        batch.save_manifest()?;
+        Ok(batch)
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Move a path below the working directory into the batch, keeping its relative location
+    ///
+    /// The manifest is rewritten after every move so an interrupted cleanup can still be restored.
+    pub fn move_in(&mut self, path: &Path) -> Result<()> {
+        let relative = path.strip_prefix(&self.root)
+            .map_err(|_| anyhow::anyhow!("'{}' is not inside the working directory", path.display()))?;
+        let directory = path.symlink_metadata()?.is_dir();
+
+        let target = self.dir.join(FILES_DIR).join(relative);
+        if let Some(parent) = target.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::rename(path, &target)?;
+
+        self.manifest.entries.push(TrashEntry {
+            original: relative.to_path_buf(),
+            directory,
+        });
+        self.save_manifest()
+    }
+
+    fn save_manifest(&self) -> Result<()> {
+        std::fs::write(self.dir.join(MANIFEST_FILE), serde_json::to_string_pretty(&self.manifest)?)?;
+        Ok(())
+    }
+}
+
+/// Batches in the trash of a working directory, oldest first
+pub fn list_batches(working_dir: &Path) -> Result<Vec<(String, TrashManifest)>> {
+    let trash = working_dir.join(TRASH_DIR);
+    if !trash.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut batches = Vec::new();
+    for entry in std::fs::read_dir(&trash)? {
+        let entry = entry?;
+        if !entry.file_type()?.is_dir() {
+            continue;
+        }
+        let name = entry.file_name().to_string_lossy().to_string();
+        batches.push((name.clone(), read_manifest(working_dir, &name)?));
+    }
+    batches.sort_by(|a, b| a.1.created_at.cmp(&b.1.created_at).then_with(|| a.0.cmp(&b.0)));
+
+    Ok(batches)
+}
+
+fn read_manifest(working_dir: &Path, batch: &str) -> Result<TrashManifest> {
+    let path = working_dir.join(TRASH_DIR).join(batch).join(MANIFEST_FILE);
+    let content = std::fs::read_to_string(&path)
+        .map_err(|e| anyhow::anyhow!("Failed to read trash manifest '{}': {}", path.display(), e))?;
+    serde_json::from_str(&content)
+        .map_err(|e| anyhow::anyhow!("Invalid trash manifest '{}': {}", path.display(), e))
+}
+
+/// Move the paths of a batch back, removing the batch once it is empty
+///
+/// Nothing is overwritten: paths whose original location is taken stay in the trash. Entries
+/// that fail to restore stay in the manifest as well, and the failures are returned together.
+pub fn restore(working_dir: &Path, batch: &str) -> Result<RestoreSummary> {
+    let dir = batch_dir(working_dir, batch)?;
+    let mut manifest = read_manifest(working_dir, batch)?;
+    let mut summary = RestoreSummary::default();
+    let mut remaining = Vec::new();
+    let mut failures = Vec::new();
+
+    // Later entries can be inside directories restored by earlier ones, so go in order
+    for entry in std::mem::take(&mut manifest.entries) {
+        let result = restore_target(working_dir, &entry.original).and_then(|original| {
+            if original.symlink_metadata().is_ok() {
+                return Ok(false);
+            }
+            std::fs::rename(dir.join(FILES_DIR).join(&entry.original), &original)?;
+            Ok(true)
+        });
+        match result {
+            Ok(true) => summary.restored.push(entry.original),
+            Ok(false) => {
+                summary.skipped.push(entry.original.clone());
+                remaining.push(entry);
+            }
+            Err(e) => {
+                failures.push(format!("Failed to restore '{}': {}", entry.original.display(), e));
+                remaining.push(entry);
+            }
+        }
+    }
+
+    if remaining.is_empty() {
+        std::fs::remove_dir_all(&dir)?;
+    } else {
+        manifest.entries = remaining;
+        std::fs::write(dir.join(MANIFEST_FILE), serde_json::to_string_pretty(&manifest)?)?;
+    }
+
+    if !failures.is_empty() {
+        anyhow::bail!("{}", failures.join("\n"));
+    }
+    Ok(summary)
+}
+
+/// Where a manifest entry goes back to, creating its parent directories
+///
+/// Each directory is checked before anything is created in it, so a manifest path with `..`
+/// or through a symlinked directory cannot lead outside the working directory.
+fn restore_target(working_dir: &Path, original: &Path) -> Result<PathBuf> {
+    if !original.components().all(|component| matches!(component, Component::Normal(_))) {
+        anyhow::bail!("'{}' is not a path inside the working directory", original.display());
+    }
+
+    let mut parent = PathBuf::new();
+    for component in original.parent().into_iter().flat_map(Path::components) {
+        parent.push(component);
+        let dir = workspace::resolve_within(working_dir, &parent)?;
+        if dir.symlink_metadata().is_err() {
+            std::fs::create_dir(&dir)?;
+        }
+    }
+    workspace::resolve_within(working_dir, original)
+}
+
+/// Permanently delete a batch
+pub fn purge(working_dir: &Path, batch: &str) -> Result<()> {
+    std::fs::remove_dir_all(batch_dir(working_dir, batch)?)?;
+    Ok(())
+}
+
+fn batch_dir(working_dir: &Path, batch: &str) -> Result<PathBuf> {
+    if batch.is_empty() || batch.contains(['/', '\\']) || batch == "." || batch == ".." {
+        anyhow::bail!("Invalid trash batch name '{}'", batch);
+    }
+    let dir = working_dir.join(TRASH_DIR).join(batch);
+    if !dir.join(MANIFEST_FILE).exists() {
+        anyhow::bail!("No trash batch named '{}' in {}", batch, working_dir.join(TRASH_DIR).display());
+    }
+    Ok(dir)
+}
+
+/// Batch directory name (`20240501-123000`) and ISO 8601 time for a moment in UTC
+fn timestamps(time: SystemTime) -> (String, String) {
+    let secs = time.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let (days, rest) = (secs / 86_400, secs % 86_400);
+    let (hour, minute, second) = (rest / 3600, rest % 3600 / 60, rest % 60);
+
+    // Civil date from days since 1970-01-01 (Howard Hinnant's algorithm)
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    (
+        format!("{:04}{:02}{:02}-{:02}{:02}{:02}", year, month, day, hour, minute, second),
+        format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z", year, month, day, hour, minute, second),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_timestamps() {
+        assert_eq!(timestamps(UNIX_EPOCH), ("19700101-000000".to_string(), "1970-01-01T00:00:00Z".to_string()));
+        let time = UNIX_EPOCH + Duration::from_secs(1_709_210_096);
+        assert_eq!(timestamps(time), ("20240229-123456".to_string(), "2024-02-29T12:34:56Z".to_string()));
+    }
+
+    #[test]
+    fn test_move_in_and_restore() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        std::fs::create_dir(root.join("split")).unwrap();
+        std::fs::write(root.join("split/01.wav"), "audio").unwrap();
+        std::fs::write(root.join("full.wav"), "full").unwrap();
+
+        let mut batch = TrashBatch::create(dir.path()).unwrap();
+        batch.move_in(&root.join("split")).unwrap();
+        batch.move_in(&root.join("full.wav")).unwrap();
+        let name = batch.dir().file_name().unwrap().to_string_lossy().to_string();
+
+        assert!(!root.join("split").exists());
+        assert_eq!(std::fs::read_to_string(batch.dir().join("files/split/01.wav")).unwrap(), "audio");
+        let batches = list_batches(dir.path()).unwrap();
+        assert_eq!(batches.len(), 1);
+        assert_eq!(batches[0].1.entries, vec![
+            TrashEntry { original: PathBuf::from("split"), directory: true },
+            TrashEntry { original: PathBuf::from("full.wav"), directory: false },
+        ]);
+
+        // An existing file at the original location is never overwritten
+        std::fs::write(root.join("full.wav"), "new").unwrap();
+        let summary = restore(dir.path(), &name).unwrap();
+        assert_eq!(summary.restored, vec![PathBuf::from("split")]);
+        assert_eq!(summary.skipped, vec![PathBuf::from("full.wav")]);
+        assert_eq!(std::fs::read_to_string(root.join("split/01.wav")).unwrap(), "audio");
+        assert_eq!(std::fs::read_to_string(root.join("full.wav")).unwrap(), "new");
+        assert_eq!(list_batches(dir.path()).unwrap()[0].1.entries.len(), 1);
+
+        purge(dir.path(), &name).unwrap();
+        assert!(list_batches(dir.path()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_restore_keeps_failed_entries() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        std::fs::write(root.join("01.wav"), "audio").unwrap();
+
+        let mut batch = TrashBatch::create(dir.path()).unwrap();
+        batch.move_in(&root.join("01.wav")).unwrap();
+        let name = batch.dir().file_name().unwrap().to_string_lossy().to_string();
+
+        // A tampered manifest pointing outside the working directory, and an entry whose file is gone
+        let mut manifest = read_manifest(dir.path(), &name).unwrap();
+        manifest.entries.insert(0, TrashEntry { original: PathBuf::from("../escape.wav"), directory: false });
+        manifest.entries.push(TrashEntry { original: PathBuf::from("missing/02.wav"), directory: false });
+        std::fs::write(batch.dir().join(MANIFEST_FILE), serde_json::to_string(&manifest).unwrap()).unwrap();
+
+        let error = restore(dir.path(), &name).unwrap_err().to_string();
+
+        assert!(error.contains("'../escape.wav' is not a path inside the working directory"), "{}", error);
+        assert!(error.contains("Failed to restore 'missing/02.wav'"), "{}", error);
+        assert_eq!(std::fs::read_to_string(root.join("01.wav")).unwrap(), "audio");
+        let entries = &list_batches(dir.path()).unwrap()[0].1.entries;
+        assert_eq!(entries.iter().map(|e| e.original.to_str().unwrap()).collect::<Vec<_>>(), ["../escape.wav", "missing/02.wav"]);
+    }
+
+    #[test]
+    fn test_batch_names() {
+        let dir = TempDir::new().unwrap();
+        let first = TrashBatch::create(dir.path()).unwrap();
+        let second = TrashBatch::create(dir.path()).unwrap();
+        assert_ne!(first.dir(), second.dir());
+
+        assert!(restore(dir.path(), "../outside").is_err());
+        assert!(purge(dir.path(), "missing").is_err());
+    }
+}
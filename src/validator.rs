@@ -1,6 +1,16 @@
-use crate::config::{Config, StepConfig, SelectedFormat};
+use crate::album_art::{detect_mime_type, format_for_path};
+use crate::config::{Config, StepConfig, SelectedFormat, ImageType, Id3Encoding, Id3Version, RunCondition, TagFormat, TagStepConfig};
+use crate::filename_pattern::FilenamePattern;
+use crate::lyrics::{is_lyrics_path, Lyrics};
+use crate::musicbrainz::MusicBrainzRelease;
+use crate::pipeline::split_step::parse_timestamp;
+use crate::pipeline::tag_step::{custom_item_key, REMOVABLE_FIELDS};
+use crate::run_report::audio_duration;
+use crate::tag_import::{entry_label, load_tag_list};
 use anyhow::Result;
-use std::path::{Path, PathBuf};
+use lofty::file::FileType;
+use lofty::tag::TagType;
+use std::path::{Component, Path, PathBuf};
 use std::collections::HashMap;
 use tracing::{info, debug};
 use glob::Pattern;
@@ -40,12 +50,15 @@ enum FileSystemEntry {
 #[derive(Debug, Clone)]
 struct FileTree {
     root: HashMap<String, FileSystemEntry>,
+    /// Lengths in seconds of the audio files steps will write, by normalized path
+    durations: HashMap<String, f64>,
 }
 
 impl FileTree {
     fn new() -> Self {
         Self {
             root: HashMap::new(),
+            durations: HashMap::new(),
         }
     }
 
@@ -81,6 +94,7 @@ impl FileTree {
         if components.is_empty() {
             return;
         }
+        self.durations.remove(&components.join("/"));
 
         let mut current = &mut self.root;
         
@@ -217,6 +231,19 @@ impl FileTree {
         if remove_recursive(&mut self.root, &components, 0) {
             debug!("FileTree: Removed {:?}", path);
         }
+        let key = components.join("/");
+        self.durations.retain(|file, _| file != &key && !file.starts_with(&format!("{}/", key)));
+    }
+
+    /// Remember the length of an audio file a step will write
+    fn set_duration(&mut self, path: &Path, seconds: f64) {
+        self.durations.insert(Self::normalize_path(path).join("/"), seconds);
+    }
+
+    /// Length of an audio file a step will write, or of one already in the working directory
+    fn duration(&self, working_dir: &Path, path: &Path) -> Option<f64> {
+        self.durations.get(&Self::normalize_path(path).join("/")).copied()
+            .or_else(|| audio_duration(&working_dir.join(path)).ok())
     }
 
     fn find_matching(&self, pattern: &str) -> Vec<PathBuf> {
@@ -377,6 +404,52 @@ pub fn validate_pipeline(
                         PathBuf::from(output_dir).join(&file.file)
                     };
                     file_tree.add_file(&output_file);
+                    if let (Ok(start), Ok(end)) = (parse_timestamp(&file.start), parse_timestamp(&file.end)) {
+                        file_tree.set_duration(&output_file, end - start);
+                    }
+                }
+            }
+            
+            StepConfig::Subtitles { input, output_dir, stream: _, files: _ } => {
+                // Check if input file exists
+                if !file_tree.exists(Path::new(input)) {
+                    let input_path = working_dir.join(input);
+                    if !input_path.exists() {
+                        result.add_error(format!(
+                            "Step {} (subtitles): Input file '{}' does not exist and will not be created by previous steps",
+                            idx + 1, input
+                        ));
+                    }
+                }
+                
+                // Create output directory
+                if output_dir != "." && !output_dir.is_empty() {
+                    file_tree.add_directory(Path::new(output_dir));
+                }
+                
+                let Some(segments) = config.subtitle_segments(idx) else {
+                    result.add_error(format!(
+                        "Step {} (subtitles): No files given and no preceding split step to take segments from",
+                        idx + 1
+                    ));
+                    continue;
+                };
+                
+                // Validate timestamps and simulate LRC file creation
+                for segment in &segments {
+                    if !validate_timestamp(&segment.start) || !validate_timestamp(&segment.end) {
+                        result.add_error(format!(
+                            "Step {} (subtitles): Invalid timestamps '{}'-'{}' for file '{}'. Expected format: h:mm:ss.SSS or h:mm:ss.SSSSSS",
+                            idx + 1, segment.start, segment.end, segment.file
+                        ));
+                    }
+                    
+                    let output_file = if output_dir == "." || output_dir.is_empty() {
+                        PathBuf::from(&segment.file)
+                    } else {
+                        PathBuf::from(output_dir).join(&segment.file)
+                    };
+                    file_tree.add_file(&output_file);
                 }
             }
             
@@ -440,19 +513,138 @@ pub fn validate_pipeline(
                             PathBuf::from(output_dir).join(output_filename)
                         };
                         
+                        let duration = file_tree.duration(working_dir, &matched_file);
                         file_tree.add_file(&output_file);
+                        if let Some(duration) = duration {
+                            file_tree.set_duration(&output_file, duration);
+                        }
                         debug!("Step {} (transcode): Simulated output file creation: {}", 
                               idx + 1, output_file.display());
                     }
                 }
             }
             
-            StepConfig::Tag { input_dir, files } => {
+            StepConfig::Tag(tag) => {
+                let TagStepConfig { input_dir, files, tags_from, defaults: _, artwork, album_art_from_video, from_filename, from_source, musicbrainz, verify: _, clear_existing: _, remove, remove_tag_types, id3 } = tag.as_ref();
+                // Entries from the track list come first so `files` can override them
+                let mut entries = Vec::new();
+                if let Some(path) = tags_from {
+                    match load_tag_list(&working_dir.join(path)) {
+                        Ok(imported) => entries = imported,
+                        Err(e) => result.add_error(format!("Step {} (tag): {}", idx + 1, e)),
+                    }
+                }
+                let imported_count = entries.len();
+                entries.extend(files.iter().cloned());
+                
+                // Defaults only fill gaps, so every value they set is checked as well
+                let merged_defaults = tag.merged_defaults(config.album.as_ref());
+                let mut tag_files: Vec<_> = entries.iter()
+                    .map(|entry| match &merged_defaults {
+                        Some(defaults) => entry.merged_with(defaults),
+                        None => entry.clone(),
+                    })
+                    .collect();
+                
+                // The video frame is extracted before any file is tagged
+                if let Some(frame) = album_art_from_video {
+                    if !file_tree.exists(Path::new(&frame.input)) && !working_dir.join(&frame.input).exists() {
+                        result.add_error(format!(
+                            "Step {} (tag): Album art video '{}' does not exist and will not be created by previous steps",
+                            idx + 1, frame.input
+                        ));
+                    }
+                    if !validate_timestamp(&frame.timestamp) {
+                        result.add_error(format!(
+                            "Step {} (tag): Invalid album art timestamp '{}'. Expected format: h:mm:ss.SSS or h:mm:ss.SSSSSS",
+                            idx + 1, frame.timestamp
+                        ));
+                    }
+                    if let Some(crop) = &frame.crop {
+                        let parts: Vec<&str> = crop.split(':').collect();
+                        if !(parts.len() == 2 || parts.len() == 4) || parts.iter().any(|p| p.trim().is_empty()) {
+                            result.add_error(format!(
+                                "Step {} (tag): Invalid album art crop '{}'. Expected w:h or w:h:x:y",
+                                idx + 1, crop
+                            ));
+                        }
+                    }
+                    if format_for_path(Path::new(&frame.output)).is_none() {
+                        result.add_error(format!(
+                            "Step {} (tag): Album art output '{}' must end in .jpg, .jpeg or .png",
+                            idx + 1, frame.output
+                        ));
+                    }
+                    
+                    file_tree.add_file(Path::new(&frame.output));
+                    frame.apply_to(&mut tag_files);
+                }
+                
+                if let Some(source) = from_source {
+                    if !file_tree.exists(Path::new(source)) && !working_dir.join(source).exists() {
+                        result.add_error(format!(
+                            "Step {} (tag): Metadata source '{}' does not exist and will not be created by previous steps",
+                            idx + 1, source
+                        ));
+                    }
+                }
+                
+                if let Some(id3) = id3 {
+                    // ID3v2.3 only defines Latin-1 and UTF-16 with a BOM
+                    if id3.version == Id3Version::V23 && matches!(id3.encoding, Some(Id3Encoding::Utf8 | Id3Encoding::Utf16be)) {
+                        result.add_error(format!(
+                            "Step {} (tag): ID3v2.3 only supports the latin1 and utf16 encodings",
+                            idx + 1
+                        ));
+                    }
+                    if id3.id3v1 && remove_tag_types.iter().flatten().any(|format| *format == TagFormat::Id3v1) {
+                        result.add_error(format!(
+                            "Step {} (tag): id3.id3v1 adds the ID3v1 tag that remove_tag_types removes",
+                            idx + 1
+                        ));
+                    }
+                }
+                
+                let release = match musicbrainz {
+                    Some(source) => match MusicBrainzRelease::load(&working_dir.join(&source.release), source.medium) {
+                        Ok(release) => Some(release),
+                        Err(e) => {
+                            result.add_error(format!("Step {} (tag): {}", idx + 1, e));
+                            None
+                        }
+                    },
+                    None => None,
+                };
+                if let Some(release) = &release {
+                    for tag_file in &tag_files {
+                        if tag_file.track.is_some() && release.for_track(tag_file.track).is_none() {
+                            result.add_warning(format!(
+                                "Step {} (tag): Track {} of '{}' has no track on the MusicBrainz medium ({} tracks)",
+                                idx + 1, tag_file.track.unwrap_or_default(), tag_file.file, release.tracks.len()
+                            ));
+                        }
+                    }
+                }
+                
+                let filename_pattern = match from_filename.as_deref().map(FilenamePattern::parse).transpose() {
+                    Ok(pattern) => pattern,
+                    Err(e) => {
+                        result.add_error(format!("Step {} (tag): {}", idx + 1, e));
+                        None
+                    }
+                };
+                
                 // Check if files to tag exist using glob matching
-                for tag_file in files {
+                for (entry_index, tag_file) in tag_files.iter().enumerate() {
                     let matches = file_tree.find_in_directory(Path::new(input_dir), &tag_file.file);
                     
-                    if matches.is_empty() {
+                    if matches.is_empty() && entry_index < imported_count {
+                        result.add_error(format!(
+                            "Step {} (tag): {} of '{}' matches no files: no files matching pattern '{}' in directory '{}'",
+                            idx + 1, entry_label(Path::new(tags_from.as_deref().unwrap_or_default()), entry_index),
+                            tags_from.as_deref().unwrap_or_default(), tag_file.file, input_dir
+                        ));
+                    } else if matches.is_empty() {
                         result.add_error(format!(
                             "Step {} (tag): No files matching pattern '{}' in directory '{}'",
                             idx + 1, tag_file.file, input_dir
@@ -462,22 +654,190 @@ pub fn validate_pipeline(
                               idx + 1, matches.len(), tag_file.file, input_dir);
                     }
                     
-                    // Check if album art file exists if specified
-                    if let Some(album_art) = &tag_file.album_art {
+                    if let Some(pattern) = &filename_pattern {
+                        for file_name in matches.iter().filter_map(|m| m.file_name()).filter_map(|n| n.to_str()) {
+                            if pattern.extract(file_name).is_none() {
+                                result.add_warning(format!(
+                                    "Step {} (tag): File name '{}' does not match pattern '{}'",
+                                    idx + 1, file_name, pattern.as_str()
+                                ));
+                            }
+                        }
+                    }
+                    
+                    // Check custom keys and removed custom fields against the tag type of every matched container
+                    let mut tag_types: Vec<TagType> = Vec::new();
+                    for tag_type in matches.iter()
+                        .filter_map(|m| m.extension())
+                        .filter_map(FileType::from_ext)
+                        .map(|file_type| file_type.primary_tag_type())
+                    {
+                        if !tag_types.contains(&tag_type) {
+                            tag_types.push(tag_type);
+                        }
+                    }
+                    
+                    let removed_custom: Vec<&String> = remove.iter().flatten()
+                        .chain(tag_file.remove.iter().flatten())
+                        .filter(|field| !REMOVABLE_FIELDS.contains(&field.as_str()))
+                        .collect();
+                    for tag_type in tag_types {
+                        for key in tag_file.custom.iter().flat_map(|custom| custom.keys()) {
+                            if let Err(e) = custom_item_key(tag_type, key) {
+                                result.add_error(format!(
+                                    "Step {} (tag): Invalid custom key for '{}': {}",
+                                    idx + 1, tag_file.file, e
+                                ));
+                            }
+                        }
+                        for field in &removed_custom {
+                            if let Err(e) = custom_item_key(tag_type, field) {
+                                result.add_error(format!(
+                                    "Step {} (tag): Invalid field to remove for '{}': {}",
+                                    idx + 1, tag_file.file, e
+                                ));
+                            }
+                        }
+                    }
+                    
+                    // Check that lyrics files exist, LRC content parses and fits within the tagged files
+                    if let Some(lyrics_value) = &tag_file.lyrics {
+                        let lyrics = if is_lyrics_path(lyrics_value) {
+                            let lyrics_path = working_dir.join(lyrics_value.trim());
+                            if !file_tree.exists(Path::new(lyrics_value.trim())) {
+                                result.add_warning(format!(
+                                    "Step {} (tag): Lyrics file '{}' does not exist",
+                                    idx + 1, lyrics_value
+                                ));
+                                None
+                            } else if let Ok(content) = std::fs::read_to_string(&lyrics_path) {
+                                match Lyrics::parse(&content) {
+                                    Ok(lyrics) => Some(lyrics),
+                                    Err(e) => {
+                                        result.add_error(format!(
+                                            "Step {} (tag): Invalid lyrics file '{}': {}",
+                                            idx + 1, lyrics_value, e
+                                        ));
+                                        None
+                                    }
+                                }
+                            } else {
+                                None
+                            }
+                        } else {
+                            match Lyrics::parse(lyrics_value) {
+                                Ok(lyrics) => Some(lyrics),
+                                Err(e) => {
+                                    result.add_error(format!(
+                                        "Step {} (tag): Invalid lyrics for '{}': {}",
+                                        idx + 1, tag_file.file, e
+                                    ));
+                                    None
+                                }
+                            }
+                        };
+                        
+                        if let Some(last_ms) = lyrics.as_ref().and_then(Lyrics::last_timestamp_ms) {
+                            for matched in &matches {
+                                let Some(duration) = file_tree.duration(working_dir, matched) else {
+                                    continue;
+                                };
+                                if f64::from(last_ms) / 1000.0 > duration {
+                                    result.add_error(format!(
+                                        "Step {} (tag): Lyrics timestamp {:.2}s exceeds the duration of '{}' ({:.2}s)",
+                                        idx + 1, f64::from(last_ms) / 1000.0, matched.display(), duration
+                                    ));
+                                }
+                            }
+                        }
+                    }
+                    
+                    // Check if album art and image files exist if specified
+                    for image in tag_file.pictures() {
+                        let album_art = &image.file;
                         if !file_tree.exists(Path::new(album_art)) {
                             result.add_warning(format!(
                                 "Step {} (tag): Album art file '{}' does not exist",
                                 idx + 1, album_art
                             ));
+                        } else if let Ok(data) = std::fs::read(working_dir.join(album_art)) {
+                            if detect_mime_type(&data).is_none() {
+                                result.add_error(format!(
+                                    "Step {} (tag): Album art file '{}' is not a JPEG, PNG, GIF, BMP or TIFF image",
+                                    idx + 1, album_art
+                                ));
+                            }
+                        }
+                    }
+                    
+                    // MP4 cannot store picture types or descriptions
+                    let typed_images = tag_file.images.iter().flatten()
+                        .any(|image| image.image_type != ImageType::FrontCover || image.description.is_some());
+                    let mp4_matches = matches.iter()
+                        .filter_map(|m| m.extension())
+                        .filter_map(FileType::from_ext)
+                        .any(|file_type| file_type.primary_tag_type() == TagType::Mp4Ilst);
+                    if typed_images && mp4_matches {
+                        result.add_warning(format!(
+                            "Step {} (tag): Picture types and descriptions for '{}' are ignored by MP4 files",
+                            idx + 1, tag_file.file
+                        ));
+                    }
+                }
+                
+                if let Some(artwork) = artwork {
+                    if artwork.quality.is_some_and(|q| q == 0 || q > 100) {
+                        result.add_error(format!(
+                            "Step {} (tag): Artwork quality must be between 1 and 100",
+                            idx + 1
+                        ));
+                    }
+                    if artwork.max_dimension == Some(0) {
+                        result.add_error(format!(
+                            "Step {} (tag): Artwork max_dimension must be greater than 0",
+                            idx + 1
+                        ));
+                    }
+                    
+                    // Simulate creation of the extra cover image files
+                    for name in &artwork.save_as {
+                        if format_for_path(Path::new(name)).is_none() {
+                            result.add_error(format!(
+                                "Step {} (tag): Artwork file '{}' must end in .jpg, .jpeg or .png",
+                                idx + 1, name
+                            ));
                         }
+                        file_tree.add_file(&PathBuf::from(input_dir).join(name));
                     }
                 }
             }
             
-            StepConfig::Cleanup { files } => {
+            StepConfig::Cleanup { files, only_created: _, mode: _, when, strict: _ } => {
+                // A cleanup that only runs after a failure leaves the files for later steps
+                let runs_on_success = *when != Some(RunCondition::Failure);
                 // Check if files or directories exist in the simulated file tree
                 for file in files {
                     let path = Path::new(file);
+                    match cleanup_depth(path) {
+                        Some(0) => {
+                            result.add_error(format!(
+                                "Step {} (cleanup): Path '{}' is the working directory itself",
+                                idx + 1, file
+                            ));
+                            continue;
+                        }
+                        None => {
+                            result.add_error(format!(
+                                "Step {} (cleanup): Path '{}' is outside the working directory",
+                                idx + 1, file
+                            ));
+                            continue;
+                        }
+                        Some(_) => {}
+                    }
+                    if !runs_on_success {
+                        continue;
+                    }
                     if !file_tree.exists(path) {
                         result.add_warning(format!(
                             "Step {} (cleanup): Path '{}' may not exist when cleanup runs",
@@ -513,6 +873,21 @@ pub fn validate_pipeline(
     Ok(result)
 }
 
+/// How many directories below the working directory a cleanup path points,
+/// or `None` when it is absolute or climbs out with `..`
+fn cleanup_depth(path: &Path) -> Option<usize> {
+    let mut depth = 0usize;
+    for component in path.components() {
+        match component {
+            Component::Normal(_) => depth += 1,
+            Component::CurDir => {}
+            Component::ParentDir => depth = depth.checked_sub(1)?,
+            Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+    Some(depth)
+}
+
 fn validate_timestamp(timestamp: &str) -> bool {
     // Expected format: h:mm:ss.SSS or h:mm:ss.SSSSSS
     let parts: Vec<&str> = timestamp.split(':').collect();
@@ -597,6 +972,7 @@ mod tests {
                 default: Some("mp3".to_string()),
             },
             settings: None,
+            album: None,
             steps: vec![],
         }
     }
@@ -609,6 +985,43 @@ mod tests {
         }
     }
 
+    fn tag_file(file: &str) -> crate::config::TagFile {
+        crate::config::TagFile {
+            file: file.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn tag_step(input_dir: &str, files: Vec<crate::config::TagFile>) -> TagStepConfig {
+        TagStepConfig {
+            input_dir: input_dir.to_string(),
+            files,
+            ..Default::default()
+        }
+    }
+
+    /// Validate `steps` without a target format in a working directory holding `files`
+    fn validate_steps(steps: Vec<StepConfig>, files: &[(&str, &[u8])]) -> ValidationResult {
+        let mut config = create_test_config();
+        config.steps = steps;
+        let format = SelectedFormat {
+            format: String::new(),
+            bitrate: None,
+            bit_depth: None,
+        };
+        let temp_dir = TempDir::new().unwrap();
+        for (path, content) in files {
+            let path = temp_dir.path().join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+        validate_pipeline(&config, &format, temp_dir.path()).unwrap()
+    }
+
+    fn validate_tag_step(step: TagStepConfig, files: &[(&str, &[u8])]) -> ValidationResult {
+        validate_steps(vec![StepConfig::Tag(Box::new(step))], files)
+    }
+
     #[test]
     fn test_file_tree_normalize_path() {
         assert_eq!(FileTree::normalize_path(Path::new("file.txt")), vec!["file.txt"]);
@@ -941,7 +1354,7 @@ mod tests {
                 output_dir: "output".to_string(),
                 files: vec!["track_01.wav".to_string()],
             },
-            crate::config::StepConfig::Tag {
+            crate::config::StepConfig::Tag(Box::new(TagStepConfig {
                 input_dir: "output".to_string(),
                 files: vec![
                     crate::config::TagFile {
@@ -958,9 +1371,11 @@ mod tests {
                         genre: None,
                         year: None,
                         comment: None,
+                        ..Default::default()
                     },
                 ],
-            },
+                ..Default::default()
+            })),
         ];
         
         let format = create_test_format();
@@ -1084,7 +1499,7 @@ mod tests {
                 args: vec![],
                 input_duration: None,
             },
-            crate::config::StepConfig::Tag {
+            crate::config::StepConfig::Tag(Box::new(TagStepConfig {
                 input_dir: ".".to_string(),
                 files: vec![
                     crate::config::TagFile {
@@ -1101,9 +1516,11 @@ mod tests {
                         genre: None,
                         year: None,
                         comment: None,
+                        ..Default::default()
                     },
                 ],
-            },
+                ..Default::default()
+            })),
         ];
         
         let format = create_test_format();
@@ -1128,6 +1545,10 @@ mod tests {
                     "nonexistent_file.wav".to_string(),
                     "nonexistent_dir".to_string(),
                 ],
+                only_created: None,
+                mode: None,
+                when: None,
+                strict: None,
             },
         ];
         
@@ -1158,7 +1579,7 @@ mod tests {
                 args: vec![],
                 input_duration: None,
             },
-            crate::config::StepConfig::Tag {
+            crate::config::StepConfig::Tag(Box::new(TagStepConfig {
                 input_dir: ".".to_string(),
                 files: vec![
                     crate::config::TagFile {
@@ -1175,9 +1596,11 @@ mod tests {
                         genre: None,
                         year: None,
                         comment: None,
+                        ..Default::default()
                     },
                 ],
-            },
+                ..Default::default()
+            })),
         ];
         
         // Use empty format to avoid format-related warnings
@@ -1259,7 +1682,7 @@ mod tests {
                 output_dir: "./final_output".to_string(),
                 files: vec!["track_01.wav".to_string(), "track_02.wav".to_string()],
             },
-            crate::config::StepConfig::Tag {
+            crate::config::StepConfig::Tag(Box::new(TagStepConfig {
                 input_dir: "./final_output".to_string(),
                 files: vec![
                     crate::config::TagFile {
@@ -1276,14 +1699,20 @@ mod tests {
                         genre: None,
                         year: None,
                         comment: None,
+                        ..Default::default()
                     },
                 ],
-            },
+                ..Default::default()
+            })),
             crate::config::StepConfig::Cleanup {
                 files: vec![
                     "extracted.wav".to_string(),
                     "split_output".to_string(),
                 ],
+                only_created: None,
+                mode: None,
+                when: None,
+                strict: None,
             },
         ];
         
@@ -1292,7 +1721,7 @@ mod tests {
         
         // Create required files
         fs::write(temp_dir.path().join("input.mkv"), "dummy video content").unwrap();
-        fs::write(temp_dir.path().join("cover.jpg"), "dummy image content").unwrap();
+        fs::write(temp_dir.path().join("cover.jpg"), b"\xFF\xD8\xFF\xE0dummy image content").unwrap();
         
         let result = validate_pipeline(&config, &format, temp_dir.path()).unwrap();
         
@@ -1300,4 +1729,494 @@ mod tests {
         assert_eq!(result.errors.len(), 0);
         assert_eq!(result.warnings.len(), 0);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_validate_pipeline_custom_tag_keys() {
+        let mut config = create_test_config();
+        let mut custom = std::collections::BTreeMap::new();
+        custom.insert("SOURCE_URL".to_string(), "https://example.com".to_string());
+        custom.insert("NOTE".to_string(), "Uppercase 4-character keys clash with ID3v2 frame IDs".to_string());
+        custom.insert("mood".to_string(), "Other 4-character keys are written as TXXX".to_string());
+        config.steps = vec![
+            crate::config::StepConfig::Ffmpeg {
+                input: "input.mkv".to_string(),
+                output: "audio.wav".to_string(),
+                args: vec![],
+                input_duration: None,
+            },
+            crate::config::StepConfig::Transcode {
+                input_dir: ".".to_string(),
+                output_dir: "output".to_string(),
+                files: vec!["audio.wav".to_string()],
+            },
+            StepConfig::Tag(Box::new(tag_step("output", vec![crate::config::TagFile {
+                custom: Some(custom),
+                ..tag_file("audio.*")
+            }]))),
+        ];
+        
+        let temp_dir = TempDir::new().unwrap();
+        fs::write(temp_dir.path().join("input.mkv"), "dummy content").unwrap();
+        
+        // MP3 output is tagged with ID3v2, which rejects the key that looks like a frame ID
+        let result = validate_pipeline(&config, &create_test_format(), temp_dir.path()).unwrap();
+        assert!(!result.is_valid);
+        assert_eq!(result.errors.len(), 1);
+        assert!(result.errors[0].contains("Invalid custom key"));
+        assert!(result.errors[0].contains("NOTE"));
+        
+        // FLAC output uses Vorbis comments, where every key is legal
+        let flac_format = SelectedFormat {
+            format: "flac".to_string(),
+            bitrate: None,
+            bit_depth: Some(24),
+        };
+        let result = validate_pipeline(&config, &flac_format, temp_dir.path()).unwrap();
+        assert!(result.is_valid);
+        assert_eq!(result.errors.len(), 0);
+    }
+
+    #[test]
+    fn test_validate_pipeline_lyrics() {
+        let lyrics = |value: &str| crate::config::TagFile {
+            lyrics: Some(value.to_string()),
+            ..tag_file("audio.wav")
+        };
+        let step = tag_step(".", vec![
+            lyrics("lyrics/valid.lrc"),
+            lyrics("lyrics/broken.lrc"),
+            lyrics("lyrics/missing.txt"),
+            lyrics("Inline lyrics\nare fine too"),
+        ]);
+
+        let result = validate_tag_step(step, &[
+            ("audio.wav", b"dummy content"),
+            ("lyrics/valid.lrc", b"[00:01.00]Hello\n[00:02.50]World\n"),
+            ("lyrics/broken.lrc", b"[00:01.00]Hello\n[00:75.00]World\n"),
+        ]);
+
+        assert!(!result.is_valid);
+        assert_eq!(result.errors.len(), 1);
+        assert!(result.errors[0].contains("broken.lrc"));
+        assert_eq!(result.warnings.len(), 1);
+        assert!(result.warnings[0].contains("missing.txt"));
+    }
+
+    #[test]
+    fn test_validate_pipeline_lyrics_duration() {
+        let lyrics = |file: &str, value: &str| crate::config::TagFile {
+            lyrics: Some(value.to_string()),
+            ..tag_file(file)
+        };
+        let steps = vec![
+            StepConfig::Split {
+                input: "input.wav".to_string(),
+                output_dir: "split".to_string(),
+                files: vec![crate::config::SplitFile {
+                    file: "01.wav".to_string(),
+                    start: "0:01:00.000".to_string(),
+                    end: "0:01:10.000".to_string(),
+                    start_seconds: 0.0,
+                    end_seconds: 0.0,
+                }],
+            },
+            StepConfig::Transcode {
+                input_dir: "split".to_string(),
+                output_dir: "output".to_string(),
+                files: vec!["*.wav".to_string()],
+            },
+            StepConfig::Tag(Box::new(tag_step("split", vec![lyrics("01.wav", "lyrics/long.lrc")]))),
+            StepConfig::Tag(Box::new(tag_step("output", vec![
+                lyrics("01.mp3", "lyrics/short.lrc"),
+                lyrics("01.mp3", "[00:10.50]Too late"),
+            ]))),
+        ];
+
+        let result = validate_steps(steps, &[
+            ("input.wav", b"dummy content"),
+            ("lyrics/long.lrc", b"[00:05.00]Hello\n[00:12.00]World\n"),
+            ("lyrics/short.lrc", b"[00:05.00]Hello\n[00:09.00]World\n"),
+        ]);
+
+        // The split file is 10 seconds long, and the transcoded copy keeps that length
+        assert!(!result.is_valid);
+        assert_eq!(result.errors.len(), 2, "{:?}", result.errors);
+        assert!(result.errors[0].contains("Step 3 (tag): Lyrics timestamp 12.00s exceeds the duration of 'split/01.wav' (10.00s)"));
+        assert!(result.errors[1].contains("Step 4 (tag): Lyrics timestamp 10.50s exceeds the duration of 'output/01.mp3'"));
+        assert!(result.warnings.is_empty());
+    }
+
+    #[test]
+    fn test_validate_pipeline_subtitles() {
+        let subtitles = || StepConfig::Subtitles {
+            input: "input.mkv".to_string(),
+            output_dir: "lyrics".to_string(),
+            stream: 0,
+            files: None,
+        };
+        let steps = vec![
+            subtitles(),
+            StepConfig::Split {
+                input: "input.mkv".to_string(),
+                output_dir: "split".to_string(),
+                files: vec![crate::config::SplitFile {
+                    file: "01.wav".to_string(),
+                    start: "0:00:00.000".to_string(),
+                    end: "0:03:00.000".to_string(),
+                    start_seconds: 0.0,
+                    end_seconds: 0.0,
+                }],
+            },
+            subtitles(),
+            StepConfig::Tag(Box::new(tag_step("split", vec![crate::config::TagFile {
+                lyrics: Some("lyrics/01.lrc".to_string()),
+                ..tag_file("01.wav")
+            }]))),
+        ];
+
+        let result = validate_steps(steps, &[("input.mkv", b"dummy content")]);
+
+        // Only the first subtitles step lacks segments; the tag step sees the generated LRC
+        assert!(!result.is_valid);
+        assert_eq!(result.errors.len(), 1);
+        assert!(result.errors[0].contains("Step 1 (subtitles)"));
+        assert!(result.warnings.is_empty());
+    }
+
+    #[test]
+    fn test_validate_pipeline_artwork() {
+        let step = TagStepConfig {
+            artwork: Some(crate::config::ArtworkOptions {
+                quality: Some(0),
+                save_as: vec!["cover.jpg".to_string(), "folder.gif".to_string()],
+                ..Default::default()
+            }),
+            ..tag_step(".", vec![crate::config::TagFile {
+                album_art: Some("cover.webp".to_string()),
+                ..tag_file("audio.wav")
+            }])
+        };
+        let steps = vec![
+            StepConfig::Tag(Box::new(step)),
+            StepConfig::Cleanup {
+                files: vec!["cover.jpg".to_string()],
+                only_created: None,
+                mode: None,
+                when: None,
+                strict: None,
+            },
+        ];
+
+        let result = validate_steps(steps, &[
+            ("audio.wav", b"dummy content"),
+            ("cover.webp", b"RIFF\x00\x00\x00\x00WEBP"),
+        ]);
+
+        assert!(!result.is_valid);
+        assert_eq!(result.errors.len(), 3);
+        assert!(result.errors[0].contains("cover.webp"));
+        assert!(result.errors[1].contains("quality"));
+        assert!(result.errors[2].contains("folder.gif"));
+        // cover.jpg is created by the tag step, so cleanup does not warn about it
+        assert!(result.warnings.is_empty());
+    }
+
+    #[test]
+    fn test_validate_pipeline_images() {
+        let image = |file: &str, image_type: ImageType| Some(vec![crate::config::TagImage {
+            file: file.to_string(),
+            image_type,
+            description: None,
+        }]);
+        let step = tag_step("output", vec![
+            crate::config::TagFile {
+                images: image("back.jpg", ImageType::BackCover),
+                ..tag_file("track.*")
+            },
+            crate::config::TagFile {
+                images: image("missing.jpg", ImageType::Artist),
+                ..tag_file("track.flac")
+            },
+        ]);
+
+        let result = validate_tag_step(step, &[
+            ("output/track.m4a", b"dummy content"),
+            ("output/track.flac", b"dummy content"),
+            ("back.jpg", b"\xFF\xD8\xFF\xE0dummy image content"),
+        ]);
+
+        assert!(result.is_valid);
+        assert_eq!(result.warnings.len(), 2);
+        assert!(result.warnings[0].contains("ignored by MP4"));
+        assert!(result.warnings[1].contains("missing.jpg"));
+    }
+
+    #[test]
+    fn test_validate_pipeline_album_art_from_video() {
+        let from_video = |frame: crate::config::VideoFrameArt| StepConfig::Tag(Box::new(TagStepConfig {
+            album_art_from_video: Some(frame),
+            ..tag_step(".", vec![tag_file("audio.wav")])
+        }));
+        let steps = vec![
+            from_video(crate::config::VideoFrameArt {
+                input: "input.mkv".to_string(),
+                timestamp: "0:01:23.000".to_string(),
+                crop: Some("1080:1080:420:0".to_string()),
+                output: "art/cover.jpg".to_string(),
+            }),
+            StepConfig::Cleanup {
+                files: vec!["art".to_string()],
+                only_created: None,
+                mode: None,
+                when: None,
+                strict: None,
+            },
+            from_video(crate::config::VideoFrameArt {
+                input: "missing.mkv".to_string(),
+                timestamp: "83".to_string(),
+                crop: Some("1080".to_string()),
+                output: "cover.bmp".to_string(),
+            }),
+        ];
+
+        let result = validate_steps(steps, &[
+            ("input.mkv", b"dummy content"),
+            ("audio.wav", b"dummy content"),
+        ]);
+
+        // The first tag step sees its extracted frame; the second has four errors
+        assert!(!result.is_valid);
+        assert_eq!(result.errors.len(), 4);
+        assert!(result.errors.iter().all(|e| e.starts_with("Step 3 (tag)")));
+        assert!(result.warnings.is_empty());
+    }
+
+    #[test]
+    fn test_validate_pipeline_tags_from() {
+        let steps = vec![
+            StepConfig::Tag(Box::new(TagStepConfig {
+                tags_from: Some("tracks.csv".to_string()),
+                ..tag_step("output", vec![tag_file("03.*")])
+            })),
+            StepConfig::Tag(Box::new(TagStepConfig {
+                tags_from: Some("broken.csv".to_string()),
+                ..tag_step("output", vec![])
+            })),
+        ];
+
+        let result = validate_steps(steps, &[
+            ("output/01.flac", b"dummy content"),
+            ("tracks.csv", b"file,title\n01.*,First\n02.*,Second\n"),
+            ("broken.csv", b"file,name\n01.*,First\n"),
+        ]);
+
+        assert!(!result.is_valid);
+        assert_eq!(result.errors.len(), 3);
+        assert!(result.errors[0].contains("Row 3 of 'tracks.csv'"));
+        assert!(result.errors[1].contains("pattern '03.*'"));
+        assert!(result.errors[2].contains("Unknown column 'name'"));
+    }
+
+    #[test]
+    fn test_validate_pipeline_from_filename() {
+        let from_filename = |pattern: &str| StepConfig::Tag(Box::new(TagStepConfig {
+            from_filename: Some(pattern.to_string()),
+            ..tag_step("split", vec![tag_file("*.wav")])
+        }));
+        let steps = vec![
+            from_filename("{track} - {artist} - {title}"),
+            from_filename("{track} - {name}"),
+        ];
+
+        let result = validate_steps(steps, &[
+            ("split/01 - Artist - Intro.wav", b"dummy content"),
+            ("split/bonus.wav", b"dummy content"),
+        ]);
+
+        assert!(!result.is_valid);
+        assert_eq!(result.errors.len(), 1);
+        assert!(result.errors[0].contains("Unknown field '{name}'"));
+        assert_eq!(result.warnings.len(), 1);
+        assert!(result.warnings[0].contains("'bonus.wav' does not match"));
+    }
+
+    #[test]
+    fn test_validate_pipeline_from_source() {
+        let step = TagStepConfig {
+            from_source: Some("other.mkv".to_string()),
+            ..tag_step(".", vec![tag_file("audio.wav")])
+        };
+
+        let result = validate_tag_step(step.clone(), &[("audio.wav", b"dummy content")]);
+
+        assert!(!result.is_valid);
+        assert_eq!(result.errors.len(), 1);
+        assert!(result.errors[0].contains("Step 1 (tag): Metadata source 'other.mkv'"));
+
+        let result = validate_tag_step(step, &[
+            ("audio.wav", b"dummy content"),
+            ("other.mkv", b"dummy content"),
+        ]);
+        assert!(result.is_valid, "{:?}", result.errors);
+    }
+
+    #[test]
+    fn test_validate_pipeline_musicbrainz() {
+        let musicbrainz = |track: u32, medium: u32| StepConfig::Tag(Box::new(TagStepConfig {
+            musicbrainz: Some(crate::config::MusicBrainzSource {
+                release: "release.json".to_string(),
+                medium,
+            }),
+            ..tag_step(".", vec![crate::config::TagFile {
+                track: Some(track),
+                ..tag_file("audio.wav")
+            }])
+        }));
+        let release = br#"{
+            "id": "rel-1",
+            "title": "Album",
+            "media": [{"position": 1, "tracks": [
+                {"id": "track-1", "position": 1, "title": "Intro", "recording": {"id": "rec-1"}}
+            ]}]
+        }"#;
+
+        let result = validate_steps(
+            vec![musicbrainz(1, 1), musicbrainz(5, 1), musicbrainz(1, 2)],
+            &[("audio.wav", b"dummy content"), ("release.json", release)],
+        );
+
+        assert!(!result.is_valid);
+        assert_eq!(result.errors.len(), 1);
+        assert!(result.errors[0].contains("Step 3 (tag): Invalid MusicBrainz release"), "{:?}", result.errors);
+        assert_eq!(result.warnings.len(), 1);
+        assert!(result.warnings[0].contains("Step 2 (tag): Track 5 of 'audio.wav' has no track"));
+    }
+
+    #[test]
+    fn test_validate_pipeline_remove_fields() {
+        let step = TagStepConfig {
+            remove: Some(vec!["comment".to_string(), "SOURCE=URL".to_string()]),
+            ..tag_step(".", vec![
+                crate::config::TagFile {
+                    remove: Some(vec!["TXXX".to_string()]),
+                    ..tag_file("audio.mp3")
+                },
+                tag_file("audio.flac"),
+            ])
+        };
+
+        let result = validate_tag_step(step, &[
+            ("audio.mp3", b"dummy content"),
+            ("audio.flac", b"dummy content"),
+        ]);
+
+        // Uppercase 4-character ID3v2 keys clash with frame IDs; '=' is not allowed in Vorbis comments
+        assert!(!result.is_valid);
+        assert_eq!(result.errors.len(), 2, "{:?}", result.errors);
+        assert!(result.errors[0].contains("Invalid field to remove for 'audio.mp3'"));
+        assert!(result.errors[0].contains("'TXXX'"));
+        assert!(result.errors[1].contains("Invalid field to remove for 'audio.flac'"));
+    }
+
+    #[test]
+    fn test_validate_pipeline_id3_options() {
+        let step = TagStepConfig {
+            remove_tag_types: Some(vec![TagFormat::Id3v1]),
+            id3: Some(crate::config::Id3Options {
+                version: Id3Version::V23,
+                encoding: Some(Id3Encoding::Utf8),
+                id3v1: true,
+            }),
+            ..tag_step(".", vec![tag_file("audio.mp3")])
+        };
+        let files: &[(&str, &[u8])] = &[("audio.mp3", b"dummy content")];
+
+        let result = validate_tag_step(step.clone(), files);
+
+        assert!(!result.is_valid);
+        assert_eq!(result.errors.len(), 2, "{:?}", result.errors);
+        assert!(result.errors[0].contains("ID3v2.3 only supports the latin1 and utf16 encodings"));
+        assert!(result.errors[1].contains("remove_tag_types"));
+
+        let step = TagStepConfig {
+            remove_tag_types: None,
+            id3: Some(crate::config::Id3Options {
+                encoding: Some(Id3Encoding::Utf16),
+                ..step.id3.unwrap()
+            }),
+            ..step
+        };
+        let result = validate_tag_step(step, files);
+        assert!(result.is_valid, "{:?}", result.errors);
+    }
+
+    #[test]
+    fn test_validate_pipeline_cleanup_outside_working_dir() {
+        let mut config = create_test_config();
+        config.steps = vec![
+            crate::config::StepConfig::Cleanup {
+                files: vec![
+                    "../source.wav".to_string(),
+                    "/tmp".to_string(),
+                    "split/..".to_string(),
+                    "split/../audio.wav".to_string(),
+                ],
+                only_created: Some(true),
+                mode: None,
+                when: None,
+                strict: None,
+            },
+        ];
+        
+        let format = SelectedFormat {
+            format: String::new(),
+            bitrate: None,
+            bit_depth: None,
+        };
+        let temp_dir = TempDir::new().unwrap();
+        fs::write(temp_dir.path().join("audio.wav"), "dummy content").unwrap();
+        
+        let result = validate_pipeline(&config, &format, temp_dir.path()).unwrap();
+        
+        assert!(!result.is_valid);
+        assert_eq!(result.errors.len(), 3, "{:?}", result.errors);
+        assert!(result.errors[0].contains("'../source.wav' is outside the working directory"));
+        assert!(result.errors[1].contains("'/tmp' is outside the working directory"));
+        assert!(result.errors[2].contains("'split/..' is the working directory itself"));
+    }
+
+    #[test]
+    fn test_validate_pipeline_cleanup_on_failure() {
+        let mut config = create_test_config();
+        config.steps = vec![
+            crate::config::StepConfig::Cleanup {
+                files: vec!["audio.wav".to_string()],
+                only_created: None,
+                mode: None,
+                when: Some(RunCondition::Failure),
+                strict: None,
+            },
+            crate::config::StepConfig::Cleanup {
+                files: vec!["audio.wav".to_string()],
+                only_created: None,
+                mode: None,
+                when: None,
+                strict: None,
+            },
+        ];
+        
+        let format = SelectedFormat {
+            format: String::new(),
+            bitrate: None,
+            bit_depth: None,
+        };
+        let temp_dir = TempDir::new().unwrap();
+        fs::write(temp_dir.path().join("audio.wav"), "dummy content").unwrap();
+        
+        let result = validate_pipeline(&config, &format, temp_dir.path()).unwrap();
+        
+        // The failure-only cleanup does not remove the file on a successful run
+        assert!(result.is_valid);
+        assert!(result.warnings.is_empty(), "{:?}", result.warnings);
+    }
+}
@@ -0,0 +1,163 @@
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+
+/// Resolve a path below the working directory, refusing anything that escapes it
+///
+/// Parent directories are canonicalized, so neither `..` nor a symlinked directory
+/// can lead outside. The last component is kept as-is: a symlink resolves to the
+/// link itself, never to its target.
+pub fn resolve_within(working_dir: &Path, path: &Path) -> Result<PathBuf> {
+    let root = working_dir.canonicalize()
+        .map_err(|e| anyhow::anyhow!("Failed to resolve working directory '{}': {}", working_dir.display(), e))?;
+    let path = working_dir.join(path);
+
+    let (Some(parent), Some(name)) = (path.parent(), path.file_name()) else {
+        anyhow::bail!("'{}' does not name a file or directory", path.display());
+    };
+    let parent = parent.canonicalize()
+        .map_err(|e| anyhow::anyhow!("Failed to resolve '{}': {}", parent.display(), e))?;
+    let resolved = parent.join(name);
+
+    if resolved == root {
+        anyhow::bail!("'{}' is the working directory itself", path.display());
+    }
+    if !resolved.starts_with(&root) {
+        anyhow::bail!("'{}' resolves to '{}', outside the working directory", path.display(), resolved.display());
+    }
+    Ok(resolved)
+}
+
+/// Expand a glob pattern, leaving out hidden files wherever the pattern has a wildcard
+///
+/// Keeps the temporary files of [`PendingOutput`], e.g. partials left behind by a crash,
+/// out of step inputs.
+pub fn glob(pattern: &str) -> Result<glob::Paths, glob::PatternError> {
+    glob::glob_with(pattern, glob::MatchOptions {
+        require_literal_leading_dot: true,
+        ..Default::default()
+    })
+}
+
+/// An output written under a temporary name next to its final path
+///
+/// `commit` renames it into place. Dropped without a commit, e.g. when the step fails or
+/// is interrupted, the temporary file is removed, so a half-written file never carries
+/// the final name.
+#[derive(Debug)]
+pub struct PendingOutput {
+    path: PathBuf,
+    temp_path: PathBuf,
+    committed: bool,
+}
+
+impl PendingOutput {
+    pub fn new(path: &Path) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            temp_path: temp_path(path),
+            committed: false,
+        }
+    }
+
+    /// Where to write; the extension is kept so FFmpeg and lofty still recognize the format
+    pub fn temp_path(&self) -> &Path {
+        &self.temp_path
+    }
+
+    pub fn commit(mut self) -> Result<()> {
+        std::fs::rename(&self.temp_path, &self.path)
+            .map_err(|e| anyhow::anyhow!("Failed to move {} into place: {}", self.path.display(), e))?;
+        self.committed = true;
+        Ok(())
+    }
+}
+
+impl Drop for PendingOutput {
+    fn drop(&mut self) {
+        if !self.committed && self.temp_path.exists() {
+            let _ = std::fs::remove_file(&self.temp_path);
+        }
+    }
+}
+
+/// `dir/.name.incomplete.ext` for `dir/name.ext`
+fn temp_path(path: &Path) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(extension) => format!(".{}.incomplete.{}", stem, extension.to_string_lossy()),
+        None => format!(".{}.incomplete", stem),
+    };
+    path.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_resolve_within() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        std::fs::create_dir(dir.path().join("split")).unwrap();
+
+        assert_eq!(resolve_within(dir.path(), Path::new("split")).unwrap(), root.join("split"));
+        assert_eq!(resolve_within(dir.path(), Path::new("split/../audio.wav")).unwrap(), root.join("audio.wav"));
+        // Missing files still resolve, their parent has to exist
+        assert_eq!(resolve_within(dir.path(), Path::new("split/01.wav")).unwrap(), root.join("split/01.wav"));
+
+        assert!(resolve_within(dir.path(), Path::new("../outside.wav")).is_err());
+        assert!(resolve_within(dir.path(), Path::new("/etc/passwd")).is_err());
+        assert!(resolve_within(dir.path(), Path::new(".")).is_err());
+        assert!(resolve_within(dir.path(), Path::new("split/..")).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_resolve_within_symlinks() {
+        let dir = TempDir::new().unwrap();
+        let outside = TempDir::new().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        std::os::unix::fs::symlink(outside.path(), dir.path().join("link")).unwrap();
+
+        // The link itself is inside, whatever it points to
+        assert_eq!(resolve_within(dir.path(), Path::new("link")).unwrap(), root.join("link"));
+        assert!(resolve_within(dir.path(), Path::new("link/file.wav")).is_err());
+    }
+
+    #[test]
+    fn test_pending_output() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("01.flac");
+
+        let pending = PendingOutput::new(&path);
+        assert_eq!(pending.temp_path(), dir.path().join(".01.incomplete.flac"));
+        std::fs::write(pending.temp_path(), "audio").unwrap();
+        pending.commit().unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "audio");
+        assert!(!dir.path().join(".01.incomplete.flac").exists());
+
+        // Abandoned outputs leave the existing file alone
+        let pending = PendingOutput::new(&path);
+        std::fs::write(pending.temp_path(), "half").unwrap();
+        drop(pending);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "audio");
+        assert!(!dir.path().join(".01.incomplete.flac").exists());
+
+        assert_eq!(PendingOutput::new(&dir.path().join("notes")).temp_path(), dir.path().join(".notes.incomplete"));
+    }
+
+    #[test]
+    fn test_glob_skips_incomplete_outputs() {
+        let dir = TempDir::new().unwrap();
+        std::fs::write(dir.path().join("01.wav"), "").unwrap();
+        // A partial left behind by a crash
+        std::fs::write(PendingOutput::new(&dir.path().join("02.wav")).temp_path(), "").unwrap();
+
+        let matched: Vec<_> = glob(&dir.path().join("*.wav").to_string_lossy())
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(matched, vec![dir.path().join("01.wav")]);
+    }
+}